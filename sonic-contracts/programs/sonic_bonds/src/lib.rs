@@ -1,9 +1,42 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use anchor_spl::token::{self, Burn, Mint, MintTo, Token, TokenAccount, Transfer};
+use anchor_lang::solana_program::hash::hashv;
 use std::mem::size_of;
 
 declare_id!("7HVxrfcxieWsDWtnn2MmcrLCjxFit821NkNPijzT9UF8");
 
+pub const CREATOR_BONDS_PAGE_SIZE: u64 = 50;
+pub const MAX_BOND_NAME_LEN: usize = 32;
+pub const MAX_BOND_DESCRIPTION_LEN: usize = 256;
+pub const MAX_BOND_METRIC_LEN: usize = 32;
+pub const MAX_BOND_METADATA_URI_LEN: usize = 200;
+pub const HOLDER_PAGE_SIZE: usize = 32;
+pub const MAX_POOL_FEE_BPS: u16 = 1000;
+pub const DEFAULT_POOL_FEE_BPS: u16 = 30;
+pub const MAX_ORDERS: usize = 128;
+pub const MAX_BATCH_ORDERS: usize = 64;
+pub const MIN_COMMIT_DEPOSIT: u64 = 10_000_000;
+pub const COMMIT_REVEAL_WINDOW_SLOTS: u64 = 150;
+pub const MAX_TRADE_FEE_BPS: u16 = 1000;
+pub const DEFAULT_TRADE_FEE_BPS: u16 = 100;
+pub const DEFAULT_REFERRAL_SHARE_BPS: u16 = 2000;
+pub const DEFAULT_INSURANCE_SHARE_BPS: u16 = 2000;
+pub const SECONDS_PER_YEAR: u64 = 31_536_000;
+pub const LIQUIDATION_BONUS_BPS: u64 = 500;
+pub const REWARD_PRECISION: u128 = 1_000_000_000_000;
+pub const MAX_PERP_LEVERAGE: u8 = 20;
+pub const FUNDING_INTERVAL_SECONDS: i64 = 3600;
+pub const PERP_LIQUIDATION_FEE_BPS: u16 = 100;
+pub const MAX_BASKET_CONSTITUENTS: usize = 10;
+pub const MAX_METRIC_COMPONENTS: usize = 8;
+pub const COMPOSITE_INDEX_SCALE: u64 = 1_000_000;
+pub const MAX_METRIC_HISTORY_CAPACITY: u32 = 600;
+pub const CIRCUIT_BREAKER_BUCKETS: usize = 8;
+pub const GOVERNANCE_VOTING_PERIOD: i64 = 3 * 24 * 60 * 60;
+pub const GOVERNANCE_QUORUM_BPS: u64 = 2000;
+pub const GOVERNANCE_PROPOSAL_THRESHOLD_BPS: u64 = 100; // Share of circulating supply a proposer must hold
+pub const GOVERNANCE_EXECUTION_PERIOD: i64 = 2 * 24 * 60 * 60; // Passed proposals expire after this
+
 #[program]
 pub mod sonic_bonds {
     use super::*;
@@ -12,9 +45,110 @@ pub mod sonic_bonds {
         let bonds_state = &mut ctx.accounts.bonds_state;
         bonds_state.authority = ctx.accounts.authority.key();
         bonds_state.bond_counter = 0;
+        bonds_state.graduation_threshold = 0;
+        bonds_state.trade_fee_bps = DEFAULT_TRADE_FEE_BPS;
+        bonds_state.referral_share_bps = DEFAULT_REFERRAL_SHARE_BPS;
+        bonds_state.insurance_share_bps = DEFAULT_INSURANCE_SHARE_BPS;
+        bonds_state.bump = ctx.bumps.bonds_state;
+        Ok(())
+    }
+
+    pub fn set_graduation_threshold(
+        ctx: Context<UpdateBondsState>,
+        graduation_threshold: u64,
+    ) -> Result<()> {
+        ctx.accounts.bonds_state.graduation_threshold = graduation_threshold;
+        Ok(())
+    }
+
+    pub fn set_trade_fees(
+        ctx: Context<UpdateBondsState>,
+        trade_fee_bps: u16,
+        referral_share_bps: u16,
+    ) -> Result<()> {
+        require!(trade_fee_bps <= MAX_TRADE_FEE_BPS, BondError::InvalidFee);
+        require!(referral_share_bps <= 10_000, BondError::InvalidFee);
+
+        let bonds_state = &mut ctx.accounts.bonds_state;
+        bonds_state.trade_fee_bps = trade_fee_bps;
+        bonds_state.referral_share_bps = referral_share_bps;
+        Ok(())
+    }
+
+    /// Grows a `BondsState` created before the fee fields existed to the current
+    /// layout and fills the new fields with their defaults.
+    pub fn migrate_bonds_state(ctx: Context<MigrateBondsState>) -> Result<()> {
+        let bonds_state_info = ctx.accounts.bonds_state.to_account_info();
+        let space = 8 + size_of::<BondsState>();
+
+        let legacy = {
+            let data = bonds_state_info.try_borrow_data()?;
+            require!(
+                data.len() < space && data[..8] == *BondsState::DISCRIMINATOR,
+                BondError::BondsStateAlreadyMigrated
+            );
+            LegacyBondsState::deserialize(&mut &data[8..])?
+        };
+        require_keys_eq!(legacy.authority, ctx.accounts.authority.key(), BondError::Unauthorized);
+
+        let rent_exempt = Rent::get()?.minimum_balance(space);
+        let top_up = rent_exempt.saturating_sub(bonds_state_info.lamports());
+        if top_up > 0 {
+            let cpi_accounts = anchor_lang::system_program::Transfer {
+                from: ctx.accounts.authority.to_account_info(),
+                to: bonds_state_info.clone(),
+            };
+            let cpi_ctx = CpiContext::new(ctx.accounts.system_program.to_account_info(), cpi_accounts);
+            anchor_lang::system_program::transfer(cpi_ctx, top_up)?;
+        }
+        bonds_state_info.realloc(space, false)?;
+
+        let bonds_state = BondsState {
+            authority: legacy.authority,
+            bond_counter: legacy.bond_counter,
+            graduation_threshold: legacy.graduation_threshold,
+            bump: legacy.bump,
+            trade_fee_bps: DEFAULT_TRADE_FEE_BPS,
+            referral_share_bps: DEFAULT_REFERRAL_SHARE_BPS,
+            insurance_share_bps: DEFAULT_INSURANCE_SHARE_BPS,
+        };
+        let mut data = bonds_state_info.try_borrow_mut_data()?;
+        bonds_state.try_serialize(&mut &mut data[..])?;
+        Ok(())
+    }
+
+    pub fn withdraw_protocol_fees(ctx: Context<WithdrawProtocolFees>, amount: u64) -> Result<()> {
+        let fee_ledger = &mut ctx.accounts.fee_ledger;
+        require!(amount > 0, BondError::InvalidAmount);
+        require!(amount <= fee_ledger.protocol_fees, BondError::InsufficientProtocolFees);
+        fee_ledger.protocol_fees -= amount;
+
+        ctx.accounts.protocol_vault.transfer_out(
+            &ctx.bumps.protocol_vault,
+            ctx.accounts.destination.to_account_info(),
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        emit!(ProtocolFeesWithdrawnEvent {
+            mint: fee_ledger.mint,
+            destination: ctx.accounts.destination.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_insurance_share(
+        ctx: Context<UpdateBondsState>,
+        insurance_share_bps: u16,
+    ) -> Result<()> {
+        require!(insurance_share_bps <= 10_000, BondError::InvalidFee);
+        ctx.accounts.bonds_state.insurance_share_bps = insurance_share_bps;
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn create_bond(
         ctx: Context<CreateBond>,
         name: String,
@@ -23,11 +157,13 @@ pub mod sonic_bonds {
         metric: String,
         total_supply: u64,
         initial_price: u64,
+        metadata_uri: String,
     ) -> Result<()> {
         require!(
             category == "game" || category == "social" || category == "content",
             BondError::InvalidCategory
         );
+        validate_bond_metadata(&name, &description, &metric, &metadata_uri)?;
 
         require!(total_supply > 0, BondError::InvalidSupply);
         require!(initial_price > 0, BondError::InvalidPrice);
@@ -45,6 +181,7 @@ pub mod sonic_bonds {
         bond.description = description;
         bond.category = category;
         bond.metric = metric;
+        bond.metadata_uri = metadata_uri;
         bond.total_supply = total_supply;
         bond.current_supply = total_supply;
         bond.price = initial_price;
@@ -52,6 +189,11 @@ pub mod sonic_bonds {
         bond.current_value = 0;
         bond.last_update = Clock::get()?.unix_timestamp;
         bond.active = true;
+        bond.trading_mode = TradingMode::Continuous;
+        bond.trade_limits = TradeLimits::default();
+        bond.presale_merkle_root = [0; 32];
+        bond.public_sale_start = 0;
+        bond.bump = ctx.bumps.bond;
 
         // Initialize market data
         let market = &mut ctx.accounts.market;
@@ -59,12 +201,28 @@ pub mod sonic_bonds {
         market.total_volume = 0;
         market.last_price = initial_price;
         market.price_change_24h = 0;
-        market.market_cap = initial_price.checked_mul(total_supply).unwrap();
+        market.quote_mint = Pubkey::default();
+        market.reserve = 0;
+        market.graduated = false;
+        market.migrated = false;
+        market.batch_epoch = 0;
+        market.batch_pending = false;
+        market.auction_active = false;
+        market.has_metric_history = false;
+        market.governance_epoch = 0;
+        market.proposal_active = false;
+        market.circuit_breaker = CircuitBreaker::default();
+        market.refresh_market_cap(bond);
+        market.bump = ctx.bumps.market;
 
-        // Add to creator's bonds
+        // Add to creator's bonds, opening a new page once the current one is full
         let creator_bonds = &mut ctx.accounts.creator_bonds;
+        let creator_bonds_page = &mut ctx.accounts.creator_bonds_page;
         creator_bonds.creator = ctx.accounts.creator.key();
-        creator_bonds.bond_ids.push(bond_id);
+        creator_bonds_page.creator = ctx.accounts.creator.key();
+        creator_bonds_page.page_index = creator_bonds.bond_count / CREATOR_BONDS_PAGE_SIZE;
+        creator_bonds_page.bond_ids.push(bond_id);
+        creator_bonds.bond_count = creator_bonds.bond_count.checked_add(1).unwrap();
 
         // Emit event
         emit!(BondCreatedEvent {
@@ -78,18 +236,98 @@ pub mod sonic_bonds {
         Ok(())
     }
 
-    pub fn update_metric(ctx: Context<UpdateMetric>, new_value: u64) -> Result<()> {
+    /// Converts a creator index written before paging (a single account holding
+    /// every bond id) into the paged layout: the ids move into page 0 and the
+    /// head account shrinks to `CreatorBonds`, refunding the freed rent.
+    pub fn migrate_creator_bonds(ctx: Context<MigrateCreatorBonds>) -> Result<()> {
+        let creator_bonds_info = ctx.accounts.creator_bonds.to_account_info();
+        let head_len = 8 + size_of::<CreatorBonds>();
+
+        let legacy = {
+            let data = creator_bonds_info.try_borrow_data()?;
+            require!(
+                data.len() > head_len && data[..8] == *CreatorBonds::DISCRIMINATOR,
+                BondError::CreatorBondsAlreadyMigrated
+            );
+            LegacyCreatorBonds::deserialize(&mut &data[8..])?
+        };
+        require!(legacy.creator == ctx.accounts.creator.key(), BondError::Unauthorized);
+
+        let creator_bonds_page = &mut ctx.accounts.creator_bonds_page;
+        creator_bonds_page.creator = legacy.creator;
+        creator_bonds_page.page_index = 0;
+        creator_bonds_page.bond_ids = legacy.bond_ids.clone();
+
+        let head = CreatorBonds {
+            creator: legacy.creator,
+            bond_count: legacy.bond_ids.len() as u64,
+        };
+        creator_bonds_info.realloc(head_len, false)?;
+        {
+            let mut data = creator_bonds_info.try_borrow_mut_data()?;
+            head.try_serialize(&mut &mut data[..])?;
+        }
+
+        let rent_exempt = Rent::get()?.minimum_balance(head_len);
+        let excess = creator_bonds_info.lamports().saturating_sub(rent_exempt);
+        if excess > 0 {
+            **creator_bonds_info.try_borrow_mut_lamports()? -= excess;
+            **ctx.accounts.creator.to_account_info().try_borrow_mut_lamports()? += excess;
+        }
+
+        Ok(())
+    }
+
+    /// Prediction markets on this bond may be passed as remaining accounts; the first
+    /// update past a market's resolve time records the value it replaces, which is the
+    /// one that was in effect at that time.
+    pub fn update_metric<'info>(
+        ctx: Context<'_, '_, 'info, 'info, UpdateMetric<'info>>,
+        new_value: u64,
+    ) -> Result<()> {
         let bond = &mut ctx.accounts.bond;
+        let now = Clock::get()?.unix_timestamp;
         
-        // Only oracle can update metrics
-        require!(
-            ctx.accounts.oracle.key() == bond.oracle,
-            BondError::InvalidOracle
-        );
+        // Composite bonds take one component reading and reprice off the recomputed index
+        let new_value = match ctx.accounts.composite_metric.as_mut() {
+            Some(composite_metric) => {
+                require_keys_eq!(
+                    bond.oracle,
+                    composite_metric.key(),
+                    BondError::InvalidOracle
+                );
+                composite_metric.record(&ctx.accounts.oracle.key(), new_value, now)?
+            }
+            None => {
+                // Only oracle can update metrics
+                require!(
+                    ctx.accounts.oracle.key() == bond.oracle,
+                    BondError::InvalidOracle
+                );
+                new_value
+            }
+        };
 
         let old_value = bond.current_value;
         bond.current_value = new_value;
-        bond.last_update = Clock::get()?.unix_timestamp;
+        bond.last_update = now;
+
+        for account_info in ctx.remaining_accounts {
+            let mut prediction_market = Account::<PredictionMarket>::try_from(account_info)?;
+            require_keys_eq!(prediction_market.bond, bond.key(), BondError::MarketBondMismatch);
+            if prediction_market.record_reading(old_value, now) {
+                prediction_market.exit(&crate::ID)?;
+            }
+        }
+
+        if ctx.accounts.market.has_metric_history {
+            let metric_history = ctx
+                .accounts
+                .metric_history
+                .as_ref()
+                .ok_or(BondError::MissingMetricHistory)?;
+            record_metric_sample(metric_history, now, new_value)?;
+        }
 
         // Update price based on metric change
         if old_value > 0 {
@@ -111,11 +349,21 @@ pub mod sonic_bonds {
             }
 
             // Update market data
-            market.market_cap = bond.price.checked_mul(bond.current_supply).unwrap();
+            market.refresh_market_cap(bond);
             
             // Calculate 24h price change
             market.price_change_24h = ((bond.price as i64 - market.last_price as i64) * 10000) 
                 / market.last_price as i64;
+
+            let slot = Clock::get()?.slot;
+            let old_price = market.last_price;
+            if market.circuit_breaker.record(old_price, bond.price, slot) {
+                emit!(TradingHaltedEvent {
+                    bond_id: bond.id,
+                    slot,
+                    resume_slot: market.circuit_breaker.resume_slot(),
+                });
+            }
         }
 
         emit!(MetricUpdatedEvent {
@@ -125,6 +373,32 @@ pub mod sonic_bonds {
             new_price: bond.price,
         });
 
+        // Each metric update closes the current batch epoch at the new price
+        if bond.trading_mode == TradingMode::BatchAuction {
+            let market = &mut ctx.accounts.market;
+            if market.batch_pending {
+                let batch_auction = ctx
+                    .accounts
+                    .batch_auction
+                    .as_mut()
+                    .ok_or(BondError::MissingBatchAuction)?;
+                let (total_buy_filled, total_sell_filled) = batch_auction.finalize(bond, market)?;
+
+                emit!(BatchAuctionClearedEvent {
+                    bond_id: bond.id,
+                    epoch: market.batch_epoch,
+                    clearing_price: batch_auction.clearing_price,
+                    buy_fill_bps: batch_auction.buy_fill_bps,
+                    sell_fill_bps: batch_auction.sell_fill_bps,
+                    total_buy_filled,
+                    total_sell_filled,
+                });
+            }
+
+            market.batch_epoch = market.batch_epoch.checked_add(1).unwrap();
+            market.batch_pending = false;
+        }
+
         Ok(())
     }
 
@@ -133,256 +407,7941 @@ pub mod sonic_bonds {
         amount: u64,
         is_buy: bool,
     ) -> Result<()> {
-        let bond = &mut ctx.accounts.bond;
+        require!(
+            ctx.accounts.bond.trading_mode == TradingMode::Continuous,
+            BondError::TradingModeMismatch
+        );
+        require!(
+            !is_buy || !ctx.accounts.bond.presale_active(Clock::get()?.unix_timestamp),
+            BondError::PresaleActive
+        );
+        execute_trade(ctx.accounts, &ctx.bumps, amount, is_buy)
+    }
+
+    pub fn commit_trade(
+        ctx: Context<CommitTrade>,
+        commitment: [u8; 32],
+        deposit: u64,
+    ) -> Result<()> {
+        let bond = &ctx.accounts.bond;
         require!(bond.active, BondError::BondInactive);
+        require!(
+            bond.trading_mode == TradingMode::CommitReveal,
+            BondError::TradingModeMismatch
+        );
+        require!(deposit >= MIN_COMMIT_DEPOSIT, BondError::InsufficientDeposit);
 
-        let market = &mut ctx.accounts.market;
-        
+        // Hold the deposit in the commitment account until reveal or forfeit
+        let cpi_accounts = anchor_lang::system_program::Transfer {
+            from: ctx.accounts.trader.to_account_info(),
+            to: ctx.accounts.trade_commitment.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.system_program.to_account_info(), cpi_accounts);
+        anchor_lang::system_program::transfer(cpi_ctx, deposit)?;
+
+        let trade_commitment = &mut ctx.accounts.trade_commitment;
+        trade_commitment.trader = ctx.accounts.trader.key();
+        trade_commitment.bond = bond.key();
+        trade_commitment.commitment = commitment;
+        trade_commitment.commit_slot = Clock::get()?.slot;
+        trade_commitment.deposit = deposit;
+        trade_commitment.bump = ctx.bumps.trade_commitment;
+
+        emit!(TradeCommittedEvent {
+            bond_id: bond.id,
+            trader: trade_commitment.trader,
+            commitment,
+            commit_slot: trade_commitment.commit_slot,
+            deposit,
+        });
+
+        Ok(())
+    }
+
+    pub fn reveal_trade(
+        ctx: Context<RevealTrade>,
+        amount: u64,
+        is_buy: bool,
+        max_price: u64,
+        salt: [u8; 32],
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.trade.bond.trading_mode == TradingMode::CommitReveal,
+            BondError::TradingModeMismatch
+        );
+        require!(
+            !is_buy || !ctx.accounts.trade.bond.presale_active(Clock::get()?.unix_timestamp),
+            BondError::PresaleActive
+        );
+
+        let trade_commitment = &ctx.accounts.trade_commitment;
+        let current_slot = Clock::get()?.slot;
+        require!(
+            current_slot > trade_commitment.commit_slot,
+            BondError::RevealTooEarly
+        );
+        require!(
+            current_slot <= trade_commitment.commit_slot + COMMIT_REVEAL_WINDOW_SLOTS,
+            BondError::RevealWindowExpired
+        );
+
+        let revealed = hashv(&[
+            &amount.to_le_bytes(),
+            &[is_buy as u8],
+            &max_price.to_le_bytes(),
+            &salt,
+        ]);
+        require!(
+            revealed.to_bytes() == trade_commitment.commitment,
+            BondError::CommitmentMismatch
+        );
+
+        // Buys cap the price paid, sells floor the price received
+        let price = ctx.accounts.trade.bond.price;
         if is_buy {
-            require!(
-                amount <= bond.current_supply,
-                BondError::InsufficientSupply
-            );
+            require!(price <= max_price, BondError::SlippageExceeded);
+        } else {
+            require!(price >= max_price, BondError::SlippageExceeded);
+        }
+
+        emit!(TradeRevealedEvent {
+            bond_id: ctx.accounts.trade.bond.id,
+            trader: trade_commitment.trader,
+            amount,
+            is_buy,
+            max_price,
+        });
+
+        // Commitment account and its deposit are returned to the trader on close
+        execute_trade(&mut ctx.accounts.trade, &ctx.bumps.trade, amount, is_buy)
+    }
+
+    pub fn forfeit_commitment(ctx: Context<ForfeitCommitment>) -> Result<()> {
+        let trade_commitment = &ctx.accounts.trade_commitment;
+        require!(
+            Clock::get()?.slot > trade_commitment.commit_slot + COMMIT_REVEAL_WINDOW_SLOTS,
+            BondError::RevealWindowOpen
+        );
+
+        // Deposit goes to the protocol, rent goes back to the trader on close
+        let deposit = trade_commitment.deposit;
+        **ctx.accounts.trade_commitment.to_account_info().try_borrow_mut_lamports()? -= deposit;
+        **ctx.accounts.authority.to_account_info().try_borrow_mut_lamports()? += deposit;
+
+        emit!(CommitmentForfeitedEvent {
+            bond_id: ctx.accounts.bond.id,
+            trader: trade_commitment.trader,
+            deposit,
+        });
+
+        Ok(())
+    }
+
+    pub fn transfer_bond(ctx: Context<TransferBond>, amount: u64) -> Result<()> {
+        require!(ctx.accounts.bond.active, BondError::BondInactive);
+        require!(amount > 0, BondError::InvalidAmount);
+
+        let market = &mut ctx.accounts.market;
+
+        let to_holder_bonds = &mut ctx.accounts.to_holder_bonds;
+        to_holder_bonds.holder = ctx.accounts.recipient.key();
+        to_holder_bonds.bond = ctx.accounts.bond.key();
+        to_holder_bonds.bump = ctx.bumps.to_holder_bonds;
+
+        ctx.accounts.bond.trade_limits.check_holding(
+            Clock::get()?.unix_timestamp,
+            to_holder_bonds.amount.checked_add(amount).unwrap(),
+        )?;
+
+        // Debit first so a shared index page sees the removal before the insertion
+        debit_holder(
+            market,
+            &mut ctx.accounts.from_holder_bonds,
+            &ctx.accounts.from_holder_page.page,
+            amount,
+        )?;
+        credit_holder(
+            market,
+            to_holder_bonds,
+            &ctx.accounts.to_holder_page.page,
+            &ctx.accounts.owner,
+            &ctx.accounts.system_program,
+            amount,
+        )?;
 
-            // Transfer payment
-            let payment_amount = amount.checked_mul(bond.price).unwrap();
+        emit!(BondTransferredEvent {
+            bond_id: ctx.accounts.bond.id,
+            from: ctx.accounts.owner.key(),
+            to: ctx.accounts.recipient.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn update_bond_metadata(
+        ctx: Context<UpdateBondMetadata>,
+        name: String,
+        description: String,
+        metric: String,
+        metadata_uri: String,
+    ) -> Result<()> {
+        validate_bond_metadata(&name, &description, &metric, &metadata_uri)?;
+
+        // Account has already been resized to fit the new values
+        let bond = &mut ctx.accounts.bond;
+        bond.name = name.clone();
+        bond.description = description;
+        bond.metric = metric.clone();
+        bond.metadata_uri = metadata_uri.clone();
+
+        emit!(BondMetadataUpdatedEvent {
+            bond_id: bond.id,
+            name,
+            metric,
+            metadata_uri,
+        });
+
+        Ok(())
+    }
+
+    pub fn create_pool(ctx: Context<CreatePool>, fee_bps: u16) -> Result<()> {
+        require!(fee_bps <= MAX_POOL_FEE_BPS, BondError::InvalidFee);
+        // Graduation moves the reserve into this pool, so both must share a quote mint
+        ctx.accounts.market.bind_quote_mint(ctx.accounts.quote_mint.key())?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.bond = ctx.accounts.bond.key();
+        pool.quote_mint = ctx.accounts.quote_mint.key();
+        pool.quote_vault = ctx.accounts.quote_vault.key();
+        pool.lp_mint = ctx.accounts.lp_mint.key();
+        pool.bond_reserve = 0;
+        pool.quote_reserve = 0;
+        pool.lp_supply = 0;
+        pool.fee_bps = fee_bps;
+        pool.bump = ctx.bumps.pool;
+
+        emit!(PoolCreatedEvent {
+            bond_id: ctx.accounts.bond.id,
+            pool: pool.key(),
+            quote_mint: pool.quote_mint,
+            fee_bps,
+        });
+
+        Ok(())
+    }
+
+    pub fn add_liquidity(
+        ctx: Context<PoolLiquidity>,
+        bond_amount: u64,
+        quote_amount: u64,
+        min_lp_out: u64,
+    ) -> Result<()> {
+        require!(ctx.accounts.bond.active, BondError::BondInactive);
+        require!(bond_amount > 0 && quote_amount > 0, BondError::InvalidAmount);
+
+        let pool = &mut ctx.accounts.pool;
+        let lp_amount = pool.liquidity_for(bond_amount, quote_amount);
+        require!(lp_amount > 0, BondError::InsufficientLiquidity);
+        require!(lp_amount >= min_lp_out, BondError::SlippageExceeded);
+
+        // Move the provider's bond units into the pool
+        debit_holder(
+            &mut ctx.accounts.market,
+            &mut ctx.accounts.holder_bonds,
+            &ctx.accounts.holder_page.page,
+            bond_amount,
+        )?;
+
+        // Transfer quote tokens into the pool vault
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.provider_quote_account.to_account_info(),
+            to: ctx.accounts.quote_vault.to_account_info(),
+            authority: ctx.accounts.provider.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, quote_amount)?;
+
+        // Mint LP tokens to the provider
+        let bond_key = ctx.accounts.bond.key();
+        let pool_seeds = &[b"pool".as_ref(), bond_key.as_ref(), &[pool.bump]];
+        let signer = &[&pool_seeds[..]];
+
+        let cpi_accounts = MintTo {
+            mint: ctx.accounts.lp_mint.to_account_info(),
+            to: ctx.accounts.provider_lp_account.to_account_info(),
+            authority: pool.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::mint_to(cpi_ctx, lp_amount)?;
+
+        pool.bond_reserve = pool.bond_reserve.checked_add(bond_amount).unwrap();
+        pool.quote_reserve = pool.quote_reserve.checked_add(quote_amount).unwrap();
+        pool.lp_supply = pool.lp_supply.checked_add(lp_amount).unwrap();
+
+        emit!(LiquidityChangedEvent {
+            bond_id: ctx.accounts.bond.id,
+            provider: ctx.accounts.provider.key(),
+            bond_amount,
+            quote_amount,
+            lp_amount,
+            is_deposit: true,
+        });
+
+        Ok(())
+    }
+
+    pub fn remove_liquidity(
+        ctx: Context<PoolLiquidity>,
+        lp_amount: u64,
+        min_bond_out: u64,
+        min_quote_out: u64,
+    ) -> Result<()> {
+        require!(lp_amount > 0, BondError::InvalidAmount);
+
+        let pool = &mut ctx.accounts.pool;
+        require!(lp_amount <= pool.lp_supply, BondError::InsufficientLiquidity);
+
+        let bond_amount = (pool.bond_reserve as u128 * lp_amount as u128 / pool.lp_supply as u128) as u64;
+        let quote_amount = (pool.quote_reserve as u128 * lp_amount as u128 / pool.lp_supply as u128) as u64;
+        require!(
+            bond_amount >= min_bond_out && quote_amount >= min_quote_out,
+            BondError::SlippageExceeded
+        );
+
+        // Burn the provider's LP tokens
+        let cpi_accounts = Burn {
+            mint: ctx.accounts.lp_mint.to_account_info(),
+            from: ctx.accounts.provider_lp_account.to_account_info(),
+            authority: ctx.accounts.provider.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::burn(cpi_ctx, lp_amount)?;
+
+        // Transfer quote tokens back to the provider
+        let bond_key = ctx.accounts.bond.key();
+        let pool_seeds = &[b"pool".as_ref(), bond_key.as_ref(), &[pool.bump]];
+        let signer = &[&pool_seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.quote_vault.to_account_info(),
+            to: ctx.accounts.provider_quote_account.to_account_info(),
+            authority: pool.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::transfer(cpi_ctx, quote_amount)?;
+
+        pool.bond_reserve = pool.bond_reserve.checked_sub(bond_amount).unwrap();
+        pool.quote_reserve = pool.quote_reserve.checked_sub(quote_amount).unwrap();
+        pool.lp_supply = pool.lp_supply.checked_sub(lp_amount).unwrap();
+
+        // Return the bond units to the provider's position
+        let holder_bonds = &mut ctx.accounts.holder_bonds;
+        holder_bonds.holder = ctx.accounts.provider.key();
+        holder_bonds.bond = bond_key;
+        holder_bonds.bump = ctx.bumps.holder_bonds;
+        credit_holder(
+            &mut ctx.accounts.market,
+            holder_bonds,
+            &ctx.accounts.holder_page.page,
+            &ctx.accounts.provider,
+            &ctx.accounts.system_program,
+            bond_amount,
+        )?;
+
+        emit!(LiquidityChangedEvent {
+            bond_id: ctx.accounts.bond.id,
+            provider: ctx.accounts.provider.key(),
+            bond_amount,
+            quote_amount,
+            lp_amount,
+            is_deposit: false,
+        });
+
+        Ok(())
+    }
+
+    pub fn swap_bonds(
+        ctx: Context<SwapBonds>,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        is_buy: bool,
+    ) -> Result<()> {
+        check_trading_allowed(&ctx.accounts.bond, &ctx.accounts.market)?;
+        require!(amount_in > 0, BondError::InvalidAmount);
+
+        let pool = &mut ctx.accounts.pool;
+        let amount_out = pool.swap_output(amount_in, is_buy);
+        require!(amount_out > 0, BondError::InsufficientLiquidity);
+        require!(amount_out >= minimum_amount_out, BondError::SlippageExceeded);
+
+        let holder_bonds = &mut ctx.accounts.holder_bonds;
+        holder_bonds.holder = ctx.accounts.trader.key();
+        holder_bonds.bond = ctx.accounts.bond.key();
+        holder_bonds.bump = ctx.bumps.holder_bonds;
+
+        if is_buy {
+            // Quote tokens in, bond units out
             let cpi_accounts = Transfer {
-                from: ctx.accounts.buyer_token_account.to_account_info(),
-                to: ctx.accounts.vault.to_account_info(),
-                authority: ctx.accounts.buyer.to_account_info(),
+                from: ctx.accounts.trader_quote_account.to_account_info(),
+                to: ctx.accounts.quote_vault.to_account_info(),
+                authority: ctx.accounts.trader.to_account_info(),
             };
             let cpi_program = ctx.accounts.token_program.to_account_info();
             let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-            token::transfer(cpi_ctx, payment_amount)?;
+            token::transfer(cpi_ctx, amount_in)?;
 
-            // Update bond state
-            bond.current_supply = bond.current_supply.checked_sub(amount).unwrap();
-            
-            // Update market data
-            market.total_volume = market.total_volume.checked_add(payment_amount).unwrap();
+            ctx.accounts.bond.trade_limits.check_buy(
+                Clock::get()?.unix_timestamp,
+                holder_bonds.amount,
+                amount_out,
+            )?;
+
+            pool.quote_reserve = pool.quote_reserve.checked_add(amount_in).unwrap();
+            pool.bond_reserve = pool.bond_reserve.checked_sub(amount_out).unwrap();
+            credit_holder(
+                &mut ctx.accounts.market,
+                holder_bonds,
+                &ctx.accounts.holder_page.page,
+                &ctx.accounts.trader,
+                &ctx.accounts.system_program,
+                amount_out,
+            )?;
         } else {
-            // Selling bonds
-            let holder_bonds = &mut ctx.accounts.holder_bonds;
-            require!(
-                amount <= holder_bonds.amount,
-                BondError::InsufficientBonds
-            );
+            // Bond units in, quote tokens out
+            debit_holder(
+                &mut ctx.accounts.market,
+                holder_bonds,
+                &ctx.accounts.holder_page.page,
+                amount_in,
+            )?;
 
-            // Calculate payment
-            let payment_amount = amount.checked_mul(bond.price).unwrap();
-            
-            // Transfer payment from vault to seller
-            let vault_authority_seeds = &[
-                b"vault_authority".as_ref(),
-                &[ctx.bumps.vault_authority]
-            ];
-            let signer = &[&vault_authority_seeds[..]];
+            let bond_key = ctx.accounts.bond.key();
+            let pool_seeds = &[b"pool".as_ref(), bond_key.as_ref(), &[pool.bump]];
+            let signer = &[&pool_seeds[..]];
 
             let cpi_accounts = Transfer {
-                from: ctx.accounts.vault.to_account_info(),
-                to: ctx.accounts.seller_token_account.to_account_info(),
-                authority: ctx.accounts.vault_authority.to_account_info(),
+                from: ctx.accounts.quote_vault.to_account_info(),
+                to: ctx.accounts.trader_quote_account.to_account_info(),
+                authority: pool.to_account_info(),
             };
             let cpi_program = ctx.accounts.token_program.to_account_info();
             let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-            token::transfer(cpi_ctx, payment_amount)?;
+            token::transfer(cpi_ctx, amount_out)?;
 
-            // Update bond state
-            bond.current_supply = bond.current_supply.checked_add(amount).unwrap();
-            holder_bonds.amount = holder_bonds.amount.checked_sub(amount).unwrap();
-            
-            // Update market data
-            market.total_volume = market.total_volume.checked_add(payment_amount).unwrap();
+            pool.bond_reserve = pool.bond_reserve.checked_add(amount_in).unwrap();
+            pool.quote_reserve = pool.quote_reserve.checked_sub(amount_out).unwrap();
         }
 
-        emit!(BondTradedEvent {
-            bond_id: bond.id,
-            trader: ctx.accounts.buyer.key(),
-            amount,
-            price: bond.price,
+        emit!(PoolSwapEvent {
+            bond_id: ctx.accounts.bond.id,
+            trader: ctx.accounts.trader.key(),
+            amount_in,
+            amount_out,
             is_buy,
+            bond_reserve: pool.bond_reserve,
+            quote_reserve: pool.quote_reserve,
         });
 
         Ok(())
     }
-}
 
-#[derive(Accounts)]
-pub struct Initialize<'info> {
-    #[account(
-        init,
-        payer = authority,
-        space = 8 + size_of::<BondsState>()
-    )]
-    pub bonds_state: Account<'info, BondsState>,
-    
-    #[account(mut)]
-    pub authority: Signer<'info>,
-    
-    pub system_program: Program<'info, System>,
-}
+    /// Migrates a graduated bond into its AMM pool. Trades stop issuing units as soon as
+    /// the threshold is crossed but do not migrate themselves, so anyone must crank this
+    /// instruction afterwards. A pool the creator already opened with create_pool is
+    /// reused; otherwise a fresh one is created at the bond's current price.
+    pub fn graduate_bond(ctx: Context<GraduateBond>) -> Result<()> {
+        let bond = &mut ctx.accounts.bond;
+        let market = &mut ctx.accounts.market;
+        require!(
+            market.has_graduated(ctx.accounts.bonds_state.graduation_threshold),
+            BondError::BondNotGraduated
+        );
+        require!(!market.migrated, BondError::BondAlreadyMigrated);
+        require!(!market.auction_active, BondError::DutchAuctionActive);
+        market.bind_quote_mint(ctx.accounts.protocol_vault.vault.mint)?;
 
-#[derive(Accounts)]
-#[instruction(name: String, description: String, category: String, metric: String)]
-pub struct CreateBond<'info> {
-    #[account(mut)]
-    pub bonds_state: Account<'info, BondsState>,
-    
-    #[account(
-        init,
-        payer = creator,
-        space = 8 + size_of::<Bond>() +
-                4 + 32 + // name: String (max 32 chars)
-                4 + 256 + // description: String (max 256 chars)
-                4 + 16 + // category: String (max 16 chars)
-                4 + 32 // metric: String (max 32 chars)
-    )]
-    pub bond: Account<'info, Bond>,
-    
-    #[account(
-        init,
-        payer = creator,
-        space = 8 + size_of::<Market>()
-    )]
-    pub market: Account<'info, Market>,
-    
-    #[account(
-        init,
-        payer = creator,
-        space = 8 + size_of::<CreatorBonds>() +
-                4 + (8 * 50), // bond_ids: Vec<u64> (max 50 bonds)
-        seeds = [b"creator_bonds", creator.key().as_ref()],
-        bump
-    )]
-    pub creator_bonds: Account<'info, CreatorBonds>,
-    
-    /// CHECK: Oracle account that will provide metric updates
-    pub oracle: AccountInfo<'info>,
-    
-    #[account(mut)]
-    pub creator: Signer<'info>,
-    
-    pub system_program: Program<'info, System>,
-}
+        let pool = &mut ctx.accounts.pool;
+        let created = pool.bond == Pubkey::default();
+        if created {
+            pool.bond = bond.key();
+            pool.quote_mint = ctx.accounts.quote_mint.key();
+            pool.quote_vault = ctx.accounts.quote_vault.key();
+            pool.lp_mint = ctx.accounts.lp_mint.key();
+            pool.fee_bps = DEFAULT_POOL_FEE_BPS;
+            pool.bump = ctx.bumps.pool;
+        }
+
+        // Pair the reserve with unsold units at the pool's ratio, or at the bond price
+        // while the pool is empty. Unsold units left unpaired are retired, and any
+        // reserve left unpaired buys units back out of the pool to be retired too.
+        let quote_amount = market.reserve;
+        let (bond_amount, paired_quote) = pool.graduation_deposit(bond.price, quote_amount, bond.current_supply);
+        let mut retired_amount = bond.current_supply.checked_sub(bond_amount).unwrap();
+
+        // Move the bond's reserves from the protocol vault into the pool
+        if quote_amount > 0 {
+            ctx.accounts.protocol_vault.transfer_out(
+                &ctx.bumps.protocol_vault,
+                ctx.accounts.quote_vault.to_account_info(),
+                &ctx.accounts.token_program,
+                quote_amount,
+            )?;
+        }
+
+        // The migrated liquidity's LP share is minted to the pool's own lock account,
+        // which no instruction can withdraw from
+        let lp_amount = pool.liquidity_for(bond_amount, paired_quote);
+        if lp_amount > 0 {
+            let bond_key = bond.key();
+            let pool_seeds = &[b"pool".as_ref(), bond_key.as_ref(), &[pool.bump]];
+            let signer = &[&pool_seeds[..]];
+
+            let cpi_accounts = MintTo {
+                mint: ctx.accounts.lp_mint.to_account_info(),
+                to: ctx.accounts.lp_lock.to_account_info(),
+                authority: pool.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token::mint_to(cpi_ctx, lp_amount)?;
+        }
+        pool.bond_reserve = pool.bond_reserve.checked_add(bond_amount).unwrap();
+        pool.quote_reserve = pool.quote_reserve.checked_add(paired_quote).unwrap();
+        pool.lp_supply = pool.lp_supply.checked_add(lp_amount).unwrap();
+
+        let leftover_quote = quote_amount.checked_sub(paired_quote).unwrap();
+        if leftover_quote > 0 {
+            let bought_amount = pool.swap_output(leftover_quote, true);
+            pool.quote_reserve = pool.quote_reserve.checked_add(leftover_quote).unwrap();
+            pool.bond_reserve = pool.bond_reserve.checked_sub(bought_amount).unwrap();
+            retired_amount = retired_amount.checked_add(bought_amount).unwrap();
+        }
+
+        bond.current_supply = 0;
+        bond.total_supply = bond.total_supply.checked_sub(retired_amount).unwrap();
+        market.reserve = 0;
+        market.graduated = true;
+        market.migrated = true;
+
+        if created {
+            emit!(PoolCreatedEvent {
+                bond_id: bond.id,
+                pool: pool.key(),
+                quote_mint: pool.quote_mint,
+                fee_bps: pool.fee_bps,
+            });
+        }
+
+        emit!(BondGraduatedEvent {
+            bond_id: bond.id,
+            pool: pool.key(),
+            market_cap: market.market_cap,
+            bond_amount,
+            quote_amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn create_order_book(ctx: Context<CreateOrderBook>, min_order_value: u64) -> Result<()> {
+        require!(min_order_value > 0, BondError::InvalidAmount);
+
+        let mut order_book = ctx.accounts.order_book.load_init()?;
+        order_book.bond = ctx.accounts.bond.key();
+        order_book.quote_mint = ctx.accounts.quote_mint.key();
+        order_book.quote_vault = ctx.accounts.quote_vault.key();
+        order_book.next_order_id = 1;
+        order_book.order_count = 0;
+        order_book.min_order_value = min_order_value;
+        order_book.bump = ctx.bumps.order_book;
+        Ok(())
+    }
+
+    pub fn place_order(
+        ctx: Context<PlaceOrder>,
+        is_bid: bool,
+        price: u64,
+        amount: u64,
+    ) -> Result<()> {
+        require!(ctx.accounts.bond.active, BondError::BondInactive);
+        require!(price > 0, BondError::InvalidPrice);
+        require!(amount > 0, BondError::InvalidAmount);
+
+        let owner = ctx.accounts.owner.key();
+        let holder_bonds = &mut ctx.accounts.holder_bonds;
+        holder_bonds.holder = owner;
+        holder_bonds.bond = ctx.accounts.bond.key();
+        holder_bonds.bump = ctx.bumps.holder_bonds;
+
+        let order_id = {
+            let mut order_book = ctx.accounts.order_book.load_mut()?;
+            // Orders below the book's minimum value would let dust fill the fixed slots
+            require!(
+                price.checked_mul(amount).unwrap() >= order_book.min_order_value,
+                BondError::OrderTooSmall
+            );
+            require!(
+                !order_book.crosses_own_order(&owner, is_bid, price),
+                BondError::SelfTrade
+            );
+            order_book.insert(owner, is_bid, price, amount)?
+        };
+
+        if is_bid {
+            let now = Clock::get()?.unix_timestamp;
+            require!(!ctx.accounts.bond.presale_active(now), BondError::PresaleActive);
+            ctx.accounts.bond.trade_limits.check_buy(now, holder_bonds.amount, amount)?;
+
+            // Escrow the full quote cost of the bid
+            let escrow_amount = price.checked_mul(amount).unwrap();
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.owner_quote_account.to_account_info(),
+                to: ctx.accounts.quote_vault.to_account_info(),
+                authority: ctx.accounts.owner.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            token::transfer(cpi_ctx, escrow_amount)?;
+        } else {
+            // Escrow the offered units in place
+            require!(amount <= holder_bonds.available(), BondError::InsufficientBonds);
+            holder_bonds.locked = holder_bonds.locked.checked_add(amount).unwrap();
+        }
+
+        emit!(OrderPlacedEvent {
+            bond_id: ctx.accounts.bond.id,
+            order_id,
+            owner,
+            is_bid,
+            price,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn cancel_order(ctx: Context<CancelOrder>, order_id: u64) -> Result<()> {
+        let owner = ctx.accounts.owner.key();
+        let (order, bump) = {
+            let mut order_book = ctx.accounts.order_book.load_mut()?;
+            let index = order_book.find(order_id).ok_or(BondError::OrderNotFound)?;
+            let order = order_book.orders[index];
+            require_keys_eq!(order.owner, owner, BondError::Unauthorized);
+            order_book.remove(index);
+            (order, order_book.bump)
+        };
+
+        if order.is_bid() {
+            // Refund the remaining escrowed quote
+            let bond_key = ctx.accounts.bond.key();
+            let order_book_seeds = &[b"order_book".as_ref(), bond_key.as_ref(), &[bump]];
+            let signer = &[&order_book_seeds[..]];
+
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.quote_vault.to_account_info(),
+                to: ctx.accounts.owner_quote_account.to_account_info(),
+                authority: ctx.accounts.order_book.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token::transfer(cpi_ctx, order.price.checked_mul(order.amount).unwrap())?;
+        } else {
+            let holder_bonds = &mut ctx.accounts.holder_bonds;
+            holder_bonds.locked = holder_bonds.locked.checked_sub(order.amount).unwrap();
+        }
+
+        emit!(OrderCancelledEvent {
+            bond_id: ctx.accounts.bond.id,
+            order_id,
+            owner,
+            remaining_amount: order.amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn match_orders(ctx: Context<MatchOrders>) -> Result<()> {
+        check_trading_allowed(&ctx.accounts.bond, &ctx.accounts.market)?;
+        // Bids are checked against the wallet cap when placed, but several can fill, so
+        // the cap also bounds what each fill credits
+        let buyer_room = ctx.accounts.bond.trade_limits.holding_room(
+            Clock::get()?.unix_timestamp,
+            ctx.accounts.buyer_holder_bonds.amount,
+        );
+
+        let (bid, ask, fill_price, fill_amount, bid_dust, ask_dust, bump) = {
+            let mut order_book = ctx.accounts.order_book.load_mut()?;
+            let bid_index = order_book.best_bid().ok_or(BondError::NoCrossingOrders)?;
+            let ask_index = order_book.best_ask().ok_or(BondError::NoCrossingOrders)?;
+            let bid = order_book.orders[bid_index];
+            let ask = order_book.orders[ask_index];
+            require!(bid.price >= ask.price, BondError::NoCrossingOrders);
+
+            // The resting (older) order sets the execution price
+            let fill_price = if bid.order_id < ask.order_id { bid.price } else { ask.price };
+            let fill_amount = bid.amount.min(ask.amount).min(buyer_room);
+            if fill_amount == 0 {
+                order_book.remove(bid_index);
+                (bid, ask, fill_price, 0, 0, 0, order_book.bump)
+            } else {
+                let bid_dust = order_book.fill(bid_index, fill_amount);
+                let ask_dust = order_book.fill(ask_index, fill_amount);
+                (bid, ask, fill_price, fill_amount, bid_dust, ask_dust, order_book.bump)
+            }
+        };
+
+        let buyer_holder_bonds = &mut ctx.accounts.buyer_holder_bonds;
+        let seller_holder_bonds = &mut ctx.accounts.seller_holder_bonds;
+        require_keys_eq!(buyer_holder_bonds.holder, bid.owner, BondError::InvalidOrderAccount);
+        require_keys_eq!(seller_holder_bonds.holder, ask.owner, BondError::InvalidOrderAccount);
+        require_keys_eq!(
+            ctx.accounts.buyer_quote_account.owner,
+            bid.owner,
+            BondError::InvalidOrderAccount
+        );
+        require_keys_eq!(
+            ctx.accounts.seller_quote_account.owner,
+            ask.owner,
+            BondError::InvalidOrderAccount
+        );
+
+        let bond_key = ctx.accounts.bond.key();
+        let order_book_seeds = &[b"order_book".as_ref(), bond_key.as_ref(), &[bump]];
+        let signer = &[&order_book_seeds[..]];
+
+        // A buyer already at the cap can't take any more, so their bid is dropped and
+        // its escrow refunded instead of blocking the book
+        if fill_amount == 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.quote_vault.to_account_info(),
+                to: ctx.accounts.buyer_quote_account.to_account_info(),
+                authority: ctx.accounts.order_book.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token::transfer(cpi_ctx, bid.price.checked_mul(bid.amount).unwrap())?;
+
+            emit!(OrderCancelledEvent {
+                bond_id: ctx.accounts.bond.id,
+                order_id: bid.order_id,
+                owner: bid.owner,
+                remaining_amount: bid.amount,
+            });
+            return Ok(());
+        }
+
+        // Release the seller's escrowed units, including any dust remainder, and hand
+        // the filled units to the buyer
+        seller_holder_bonds.locked = seller_holder_bonds
+            .locked
+            .checked_sub(fill_amount.checked_add(ask_dust).unwrap())
+            .unwrap();
+        debit_holder(
+            &mut ctx.accounts.market,
+            seller_holder_bonds,
+            &ctx.accounts.seller_holder_page.page,
+            fill_amount,
+        )?;
+        credit_holder(
+            &mut ctx.accounts.market,
+            buyer_holder_bonds,
+            &ctx.accounts.buyer_holder_page.page,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+            fill_amount,
+        )?;
+
+        // Pay the seller and refund any price improvement to the buyer
+        let payment_amount = fill_price.checked_mul(fill_amount).unwrap();
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.quote_vault.to_account_info(),
+            to: ctx.accounts.seller_quote_account.to_account_info(),
+            authority: ctx.accounts.order_book.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::transfer(cpi_ctx, payment_amount)?;
+
+        let refund_amount = (bid.price - fill_price)
+            .checked_mul(fill_amount)
+            .unwrap()
+            .checked_add(bid.price.checked_mul(bid_dust).unwrap())
+            .unwrap();
+        if refund_amount > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.quote_vault.to_account_info(),
+                to: ctx.accounts.buyer_quote_account.to_account_info(),
+                authority: ctx.accounts.order_book.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token::transfer(cpi_ctx, refund_amount)?;
+        }
+
+        let market = &mut ctx.accounts.market;
+        market.total_volume = market.total_volume.checked_add(payment_amount).unwrap();
+
+        emit!(OrderFilledEvent {
+            bond_id: ctx.accounts.bond.id,
+            bid_order_id: bid.order_id,
+            ask_order_id: ask.order_id,
+            buyer: bid.owner,
+            seller: ask.owner,
+            price: fill_price,
+            amount: fill_amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_trading_mode(ctx: Context<SetTradingMode>, trading_mode: TradingMode) -> Result<()> {
+        apply_trading_mode(&mut ctx.accounts.bond, &ctx.accounts.market, trading_mode)
+    }
+
+    pub fn set_pool_fee(ctx: Context<SetPoolFee>, fee_bps: u16) -> Result<()> {
+        apply_pool_fee(&ctx.accounts.bond, &mut ctx.accounts.pool, fee_bps)
+    }
+
+    pub fn submit_batch_order(
+        ctx: Context<SubmitBatchOrder>,
+        is_buy: bool,
+        amount: u64,
+        limit_price: u64,
+    ) -> Result<()> {
+        let bond = &ctx.accounts.bond;
+        check_trading_allowed(bond, &ctx.accounts.market)?;
+        require!(
+            bond.trading_mode == TradingMode::BatchAuction,
+            BondError::TradingModeMismatch
+        );
+        require!(amount > 0, BondError::InvalidAmount);
+        require!(limit_price > 0, BondError::InvalidPrice);
+
+        let market = &mut ctx.accounts.market;
+        require!(!market.graduated, BondError::BondGraduated);
+        require!(!market.auction_active, BondError::DutchAuctionActive);
+        market.bind_quote_mint(ctx.accounts.protocol_vault.vault.mint)?;
+
+        let owner = ctx.accounts.owner.key();
+        let holder_bonds = &mut ctx.accounts.holder_bonds;
+        holder_bonds.holder = owner;
+        holder_bonds.bond = bond.key();
+        holder_bonds.bump = ctx.bumps.holder_bonds;
+
+        let batch_auction = &mut ctx.accounts.batch_auction;
+        batch_auction.bond = bond.key();
+        batch_auction.epoch = market.batch_epoch;
+        batch_auction.bump = ctx.bumps.batch_auction;
+        require!(
+            batch_auction.orders.len() < MAX_BATCH_ORDERS,
+            BondError::BatchAuctionFull
+        );
+
+        if is_buy {
+            let now = Clock::get()?.unix_timestamp;
+            require!(!bond.presale_active(now), BondError::PresaleActive);
+            bond.trade_limits.check_buy(now, holder_bonds.amount, amount)?;
+
+            // Escrow the worst-case cost plus its trading fee in the protocol vault
+            let escrow_amount = batch_escrow(&ctx.accounts.bonds_state, amount, limit_price);
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.owner_token_account.to_account_info(),
+                to: ctx.accounts.protocol_vault.vault.to_account_info(),
+                authority: ctx.accounts.owner.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            token::transfer(cpi_ctx, escrow_amount)?;
+        } else {
+            require!(amount <= holder_bonds.available(), BondError::InsufficientBonds);
+            holder_bonds.locked = holder_bonds.locked.checked_add(amount).unwrap();
+        }
+
+        batch_auction.orders.push(BatchOrder {
+            owner,
+            is_buy,
+            amount,
+            limit_price,
+            settled: false,
+        });
+        market.batch_pending = true;
+
+        emit!(BatchOrderSubmittedEvent {
+            bond_id: bond.id,
+            epoch: batch_auction.epoch,
+            order_index: (batch_auction.orders.len() - 1) as u64,
+            owner,
+            is_buy,
+            amount,
+            limit_price,
+        });
+
+        Ok(())
+    }
+
+    pub fn settle_batch_order(ctx: Context<SettleBatchOrder>, order_index: u64) -> Result<()> {
+        check_not_halted(&ctx.accounts.market)?;
+        require!(!ctx.accounts.market.auction_active, BondError::DutchAuctionActive);
+        ctx.accounts.market.bind_quote_mint(ctx.accounts.protocol_vault.vault.mint)?;
+
+        let batch_auction = &mut ctx.accounts.batch_auction;
+        require!(batch_auction.finalized, BondError::BatchAuctionNotFinalized);
+
+        let fee_ledger = &mut ctx.accounts.fee_ledger;
+        fee_ledger.mint = ctx.accounts.protocol_vault.vault.mint;
+        fee_ledger.bump = ctx.bumps.fee_ledger;
+
+        let clearing_price = batch_auction.clearing_price;
+        let buy_fill_bps = batch_auction.buy_fill_bps;
+        let sell_fill_bps = batch_auction.sell_fill_bps;
+        let order = batch_auction
+            .orders
+            .get_mut(order_index as usize)
+            .ok_or(BondError::OrderNotFound)?;
+        require!(!order.settled, BondError::OrderAlreadySettled);
+        order.settled = true;
+        let order = order.clone();
+
+        let holder_bonds = &mut ctx.accounts.holder_bonds;
+        require_keys_eq!(holder_bonds.holder, order.owner, BondError::InvalidOrderAccount);
+        require_keys_eq!(
+            ctx.accounts.owner_token_account.owner,
+            order.owner,
+            BondError::InvalidOrderAccount
+        );
+
+        let filled = BatchAuction::filled_amount(&order, clearing_price, buy_fill_bps, sell_fill_bps);
+        let (filled, payout) = if order.is_buy {
+            let credited = return_over_cap(
+                &mut ctx.accounts.bond,
+                &mut ctx.accounts.market,
+                holder_bonds.amount,
+                filled,
+                clearing_price,
+            )?;
+            credit_holder(
+                &mut ctx.accounts.market,
+                holder_bonds,
+                &ctx.accounts.holder_page.page,
+                &ctx.accounts.payer,
+                &ctx.accounts.system_program,
+                credited,
+            )?;
+
+            // Buyers pay the trading fee on what they received out of the escrow, and get
+            // back whatever the escrow did not pay for
+            let escrow_amount =
+                batch_escrow(&ctx.accounts.bonds_state, order.amount, order.limit_price);
+            let payment_amount = credited.checked_mul(clearing_price).unwrap();
+            let fee_amount = charge_settlement_fee(
+                &ctx.accounts.bonds_state,
+                &mut ctx.accounts.fee_ledger,
+                payment_amount,
+                escrow_amount.checked_sub(payment_amount).unwrap(),
+            );
+            let refund_amount = escrow_amount
+                .checked_sub(payment_amount)
+                .unwrap()
+                .checked_sub(fee_amount)
+                .unwrap();
+            (credited, refund_amount)
+        } else {
+            holder_bonds.locked = holder_bonds.locked.checked_sub(order.amount).unwrap();
+            debit_holder(
+                &mut ctx.accounts.market,
+                holder_bonds,
+                &ctx.accounts.holder_page.page,
+                filled,
+            )?;
+
+            // Sellers have the fee withheld from their proceeds, as on the curve
+            let payment_amount = filled.checked_mul(clearing_price).unwrap();
+            let fee_amount = charge_settlement_fee(
+                &ctx.accounts.bonds_state,
+                &mut ctx.accounts.fee_ledger,
+                payment_amount,
+                payment_amount,
+            );
+            (filled, payment_amount.checked_sub(fee_amount).unwrap())
+        };
+
+        if payout > 0 {
+            ctx.accounts.protocol_vault.transfer_out(
+                &ctx.bumps.protocol_vault,
+                ctx.accounts.owner_token_account.to_account_info(),
+                &ctx.accounts.token_program,
+                payout,
+            )?;
+        }
+
+        emit!(BatchOrderSettledEvent {
+            bond_id: ctx.accounts.bond.id,
+            epoch: batch_auction.epoch,
+            order_index,
+            owner: order.owner,
+            is_buy: order.is_buy,
+            filled_amount: filled,
+            price: clearing_price,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_trade_limits(ctx: Context<SetTradeLimits>, trade_limits: TradeLimits) -> Result<()> {
+        let bond = &mut ctx.accounts.bond;
+        bond.trade_limits = trade_limits;
+
+        emit!(TradeLimitsUpdatedEvent {
+            bond_id: bond.id,
+            trade_limits,
+        });
+
+        Ok(())
+    }
+
+    pub fn configure_presale(
+        ctx: Context<ConfigurePresale>,
+        merkle_root: [u8; 32],
+        public_sale_start: i64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let bond = &mut ctx.accounts.bond;
+
+        // A presale can only be set up before the first unit is sold, and the allowlist
+        // can't be changed or reopened once the public sale has begun
+        if bond.public_sale_start == 0 {
+            require!(bond.current_supply == bond.total_supply, BondError::PublicSaleStarted);
+        } else {
+            require!(now < bond.public_sale_start, BondError::PublicSaleStarted);
+        }
+        require!(public_sale_start > now, BondError::InvalidPresaleSchedule);
+
+        bond.presale_merkle_root = merkle_root;
+        bond.public_sale_start = public_sale_start;
+
+        emit!(PresaleConfiguredEvent {
+            bond_id: bond.id,
+            merkle_root,
+            public_sale_start,
+        });
+
+        Ok(())
+    }
+
+    pub fn presale_buy(
+        ctx: Context<PresaleBuy>,
+        amount: u64,
+        allocation: u64,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        let bond = &ctx.accounts.trade.bond;
+        require!(
+            bond.presale_active(Clock::get()?.unix_timestamp),
+            BondError::PresaleInactive
+        );
+
+        // Leaf commits to the buyer and their allocation
+        let buyer = ctx.accounts.buyer.key();
+        let leaf = hashv(&[buyer.as_ref(), &allocation.to_le_bytes()]).to_bytes();
+        require!(
+            verify_merkle_proof(&proof, bond.presale_merkle_root, leaf),
+            BondError::InvalidMerkleProof
+        );
+
+        let presale_allocation = &mut ctx.accounts.presale_allocation;
+        presale_allocation.buyer = buyer;
+        presale_allocation.bond = bond.key();
+        presale_allocation.bump = ctx.bumps.presale_allocation;
+        presale_allocation.purchased = presale_allocation.purchased.checked_add(amount).unwrap();
+        require!(
+            presale_allocation.purchased <= allocation,
+            BondError::AllocationExceeded
+        );
+
+        execute_trade(&mut ctx.accounts.trade, &ctx.bumps.trade, amount, true)
+    }
+
+    pub fn start_dutch_auction(
+        ctx: Context<StartDutchAuction>,
+        tranche_amount: u64,
+        start_price: u64,
+        floor_price: u64,
+        start_time: i64,
+        end_time: i64,
+    ) -> Result<()> {
+        let bond = &mut ctx.accounts.bond;
+        require!(bond.active, BondError::BondInactive);
+
+        // Only a bond that hasn't sold anything yet can open with an auction
+        require!(
+            bond.current_supply == bond.total_supply,
+            BondError::BondAlreadyTrading
+        );
+        require!(!ctx.accounts.market.batch_pending, BondError::BatchAuctionPending);
+        require!(
+            tranche_amount > 0 && tranche_amount <= bond.current_supply,
+            BondError::InvalidSupply
+        );
+        require!(
+            floor_price > 0 && start_price > floor_price,
+            BondError::InvalidPrice
+        );
+        require!(
+            end_time > start_time && end_time > Clock::get()?.unix_timestamp,
+            BondError::InvalidAuctionSchedule
+        );
+
+        let dutch_auction = &mut ctx.accounts.dutch_auction;
+        dutch_auction.bond = bond.key();
+        dutch_auction.tranche_amount = tranche_amount;
+        dutch_auction.start_price = start_price;
+        dutch_auction.floor_price = floor_price;
+        dutch_auction.start_time = start_time;
+        dutch_auction.end_time = end_time;
+        dutch_auction.sold_amount = 0;
+        dutch_auction.last_bid_price = 0;
+        dutch_auction.clearing_price = 0;
+        dutch_auction.cleared = false;
+        dutch_auction.bump = ctx.bumps.dutch_auction;
+
+        // The tranche is held out of primary supply until the auction clears
+        bond.current_supply = bond.current_supply.checked_sub(tranche_amount).unwrap();
+        ctx.accounts.market.auction_active = true;
+
+        emit!(DutchAuctionStartedEvent {
+            bond_id: bond.id,
+            tranche_amount,
+            start_price,
+            floor_price,
+            start_time,
+            end_time,
+        });
+
+        Ok(())
+    }
+
+    pub fn bid_dutch_auction(ctx: Context<BidDutchAuction>, amount: u64) -> Result<()> {
+        check_trading_allowed(&ctx.accounts.bond, &ctx.accounts.market)?;
+        require!(amount > 0, BondError::InvalidAmount);
+        ctx.accounts.market.bind_quote_mint(ctx.accounts.protocol_vault.vault.mint)?;
+
+        let now = Clock::get()?.unix_timestamp;
+        require!(!ctx.accounts.bond.presale_active(now), BondError::PresaleActive);
+        let dutch_auction = &mut ctx.accounts.dutch_auction;
+        require!(!dutch_auction.cleared, BondError::DutchAuctionCleared);
+        require!(
+            now >= dutch_auction.start_time && now < dutch_auction.end_time,
+            BondError::DutchAuctionNotOpen
+        );
+
+        // Bids past the remaining tranche are trimmed rather than rejected
+        let fill_amount = amount.min(
+            dutch_auction
+                .tranche_amount
+                .checked_sub(dutch_auction.sold_amount)
+                .unwrap(),
+        );
+        let price = dutch_auction.current_price(now);
+        let payment_amount = fill_amount.checked_mul(price).unwrap();
+        let fee_amount = ctx.accounts.bonds_state.trade_fee(payment_amount);
+
+        // Units already won in this auction count toward the wallet cap
+        let pending_amount = ctx.accounts.dutch_bid.amount;
+        ctx.accounts.bond.trade_limits.check_buy(
+            now,
+            ctx.accounts.holder_bonds.amount.checked_add(pending_amount).unwrap(),
+            fill_amount,
+        )?;
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.bidder_token_account.to_account_info(),
+            to: ctx.accounts.protocol_vault.vault.to_account_info(),
+            authority: ctx.accounts.bidder.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, payment_amount.checked_add(fee_amount).unwrap())?;
+
+        let holder_bonds = &mut ctx.accounts.holder_bonds;
+        holder_bonds.holder = ctx.accounts.bidder.key();
+        holder_bonds.bond = ctx.accounts.bond.key();
+        holder_bonds.bump = ctx.bumps.holder_bonds;
+
+        let dutch_bid = &mut ctx.accounts.dutch_bid;
+        dutch_bid.bidder = ctx.accounts.bidder.key();
+        dutch_bid.auction = dutch_auction.key();
+        dutch_bid.amount = dutch_bid.amount.checked_add(fill_amount).unwrap();
+        // The trading fee is escrowed with the bid and charged on the clearing price
+        dutch_bid.paid = dutch_bid
+            .paid
+            .checked_add(payment_amount.checked_add(fee_amount).unwrap())
+            .unwrap();
+        dutch_bid.bump = ctx.bumps.dutch_bid;
+
+        dutch_auction.sold_amount = dutch_auction.sold_amount.checked_add(fill_amount).unwrap();
+        dutch_auction.last_bid_price = price;
+
+        emit!(DutchAuctionBidEvent {
+            bond_id: ctx.accounts.bond.id,
+            bidder: dutch_bid.bidder,
+            amount: fill_amount,
+            price,
+        });
+
+        // Selling out the tranche clears the auction at this bid's price
+        if dutch_auction.sold_amount == dutch_auction.tranche_amount {
+            clear_dutch_auction(
+                dutch_auction,
+                &mut ctx.accounts.bond,
+                &mut ctx.accounts.market,
+                price,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn finalize_dutch_auction(ctx: Context<FinalizeDutchAuction>) -> Result<()> {
+        let dutch_auction = &mut ctx.accounts.dutch_auction;
+        require!(!dutch_auction.cleared, BondError::DutchAuctionCleared);
+        require!(
+            Clock::get()?.unix_timestamp >= dutch_auction.end_time,
+            BondError::DutchAuctionNotEnded
+        );
+
+        // An undersubscribed auction clears at the lowest price anyone bid
+        let clearing_price = if dutch_auction.sold_amount > 0 {
+            dutch_auction.last_bid_price
+        } else {
+            dutch_auction.floor_price
+        };
+        clear_dutch_auction(
+            dutch_auction,
+            &mut ctx.accounts.bond,
+            &mut ctx.accounts.market,
+            clearing_price,
+        )
+    }
+
+    pub fn settle_dutch_bid(ctx: Context<SettleDutchBid>) -> Result<()> {
+        ctx.accounts.market.bind_quote_mint(ctx.accounts.protocol_vault.vault.mint)?;
+        let fee_ledger = &mut ctx.accounts.fee_ledger;
+        fee_ledger.mint = ctx.accounts.protocol_vault.vault.mint;
+        fee_ledger.bump = ctx.bumps.fee_ledger;
+
+        let dutch_auction = &ctx.accounts.dutch_auction;
+        require!(dutch_auction.cleared, BondError::DutchAuctionNotCleared);
+
+        let dutch_bid = &mut ctx.accounts.dutch_bid;
+        require!(!dutch_bid.settled, BondError::OrderAlreadySettled);
+        dutch_bid.settled = true;
+
+        let holder_bonds = &mut ctx.accounts.holder_bonds;
+        require_keys_eq!(holder_bonds.holder, dutch_bid.bidder, BondError::InvalidOrderAccount);
+        require_keys_eq!(
+            ctx.accounts.bidder_token_account.owner,
+            dutch_bid.bidder,
+            BondError::InvalidOrderAccount
+        );
+
+        let credited = return_over_cap(
+            &mut ctx.accounts.bond,
+            &mut ctx.accounts.market,
+            holder_bonds.amount,
+            dutch_bid.amount,
+            dutch_auction.clearing_price,
+        )?;
+        credit_holder(
+            &mut ctx.accounts.market,
+            holder_bonds,
+            &ctx.accounts.holder_page.page,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+            credited,
+        )?;
+
+        // Everyone pays the clearing price plus its trading fee, so refund what was paid
+        // above that, including the value of any units returned over the cap
+        let payment_amount = credited.checked_mul(dutch_auction.clearing_price).unwrap();
+        let fee_amount = charge_settlement_fee(
+            &ctx.accounts.bonds_state,
+            &mut ctx.accounts.fee_ledger,
+            payment_amount,
+            dutch_bid.paid.checked_sub(payment_amount).unwrap(),
+        );
+        let refund_amount = dutch_bid
+            .paid
+            .checked_sub(payment_amount)
+            .unwrap()
+            .checked_sub(fee_amount)
+            .unwrap();
+        if refund_amount > 0 {
+            ctx.accounts.protocol_vault.transfer_out(
+                &ctx.bumps.protocol_vault,
+                ctx.accounts.bidder_token_account.to_account_info(),
+                &ctx.accounts.token_program,
+                refund_amount,
+            )?;
+        }
+
+        emit!(DutchBidSettledEvent {
+            bond_id: ctx.accounts.bond.id,
+            bidder: dutch_bid.bidder,
+            amount: credited,
+            refund_amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn register_referrer(ctx: Context<RegisterReferrer>) -> Result<()> {
+        let referrer_stats = &mut ctx.accounts.referrer_stats;
+        referrer_stats.referrer = ctx.accounts.referrer.key();
+        referrer_stats.claimable = 0;
+        referrer_stats.lifetime_volume = 0;
+        referrer_stats.lifetime_rewards = 0;
+        referrer_stats.bump = ctx.bumps.referrer_stats;
+        Ok(())
+    }
+
+    pub fn claim_referral_rewards(ctx: Context<ClaimReferralRewards>) -> Result<()> {
+        let referrer_stats = &mut ctx.accounts.referrer_stats;
+        let amount = referrer_stats.claimable;
+        require!(amount > 0, BondError::NothingToClaim);
+        referrer_stats.claimable = 0;
+
+        ctx.accounts.protocol_vault.transfer_out(
+            &ctx.bumps.protocol_vault,
+            ctx.accounts.referrer_token_account.to_account_info(),
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        emit!(ReferralRewardsClaimedEvent {
+            referrer: referrer_stats.referrer,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn create_lending_pool(
+        ctx: Context<CreateLendingPool>,
+        ltv_bps: u16,
+        liquidation_threshold_bps: u16,
+        interest_rate_bps: u16,
+    ) -> Result<()> {
+        require!(
+            ltv_bps > 0
+                && ltv_bps < liquidation_threshold_bps
+                && liquidation_threshold_bps <= 10_000,
+            BondError::InvalidLendingParams
+        );
+
+        // Loans are valued at bond.price, so they must be drawn in the mint it is quoted in
+        ctx.accounts.market.bind_quote_mint(ctx.accounts.quote_mint.key())?;
+
+        let lending_pool = &mut ctx.accounts.lending_pool;
+        lending_pool.bond = ctx.accounts.bond.key();
+        lending_pool.quote_mint = ctx.accounts.quote_mint.key();
+        lending_pool.quote_vault = ctx.accounts.lending_vault.key();
+        lending_pool.total_deposits = 0;
+        lending_pool.total_shares = 0;
+        lending_pool.total_borrowed = 0;
+        lending_pool.accrued_interest = 0;
+        lending_pool.bad_debt = 0;
+        lending_pool.last_accrual = Clock::get()?.unix_timestamp;
+        lending_pool.ltv_bps = ltv_bps;
+        lending_pool.liquidation_threshold_bps = liquidation_threshold_bps;
+        lending_pool.interest_rate_bps = interest_rate_bps;
+        lending_pool.bump = ctx.bumps.lending_pool;
+
+        Ok(())
+    }
+
+    pub fn deposit_lending_liquidity(ctx: Context<LenderLiquidity>, amount: u64) -> Result<()> {
+        require!(amount > 0, BondError::InvalidAmount);
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.lender_token_account.to_account_info(),
+            to: ctx.accounts.lending_vault.to_account_info(),
+            authority: ctx.accounts.lender.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, amount)?;
+
+        let lending_pool = &mut ctx.accounts.lending_pool;
+        lending_pool.accrue(Clock::get()?.unix_timestamp);
+        let shares = lending_pool.shares_for(amount);
+        require!(shares > 0, BondError::InvalidAmount);
+        lending_pool.total_deposits = lending_pool.total_deposits.checked_add(amount).unwrap();
+        lending_pool.total_shares = lending_pool.total_shares.checked_add(shares).unwrap();
+
+        let lender_deposit = &mut ctx.accounts.lender_deposit;
+        lender_deposit.lender = ctx.accounts.lender.key();
+        lender_deposit.lending_pool = lending_pool.key();
+        lender_deposit.shares = lender_deposit.shares.checked_add(shares).unwrap();
+        lender_deposit.bump = ctx.bumps.lender_deposit;
+
+        Ok(())
+    }
+
+    pub fn withdraw_lending_liquidity(ctx: Context<LenderLiquidity>, shares: u64) -> Result<()> {
+        let lender_deposit = &mut ctx.accounts.lender_deposit;
+        require!(
+            shares > 0 && shares <= lender_deposit.shares,
+            BondError::InvalidAmount
+        );
+
+        let lending_pool = &mut ctx.accounts.lending_pool;
+        lending_pool.accrue(Clock::get()?.unix_timestamp);
+        let amount = lending_pool.value_of(shares);
+
+        // Lenders can only pull what isn't currently lent out
+        require!(
+            amount <= ctx.accounts.lending_vault.amount,
+            BondError::InsufficientLiquidity
+        );
+
+        lender_deposit.shares -= shares;
+        lending_pool.total_shares = lending_pool.total_shares.checked_sub(shares).unwrap();
+        lending_pool.total_deposits = lending_pool.total_deposits.checked_sub(amount).unwrap();
+
+        let bond_key = ctx.accounts.bond.key();
+        let lending_pool_seeds = &[
+            b"lending_pool".as_ref(),
+            bond_key.as_ref(),
+            &[ctx.accounts.lending_pool.bump]
+        ];
+        let signer = &[&lending_pool_seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.lending_vault.to_account_info(),
+            to: ctx.accounts.lender_token_account.to_account_info(),
+            authority: ctx.accounts.lending_pool.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::transfer(cpi_ctx, amount)?;
+
+        Ok(())
+    }
+
+    pub fn borrow(ctx: Context<Borrow>, collateral_amount: u64, borrow_amount: u64) -> Result<()> {
+        let bond = &ctx.accounts.bond;
+        check_trading_allowed(bond, &ctx.accounts.market)?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let lending_pool = &mut ctx.accounts.lending_pool;
+        let loan = &mut ctx.accounts.loan;
+        loan.borrower = ctx.accounts.borrower.key();
+        loan.bond = bond.key();
+        loan.bump = ctx.bumps.loan;
+        lending_pool.accrue(now);
+        loan.accrue(lending_pool.interest_rate_bps, now);
+
+        // Lock the added collateral so it can't be sold or transferred
+        let holder_bonds = &mut ctx.accounts.holder_bonds;
+        require!(
+            collateral_amount <= holder_bonds.available(),
+            BondError::InsufficientBonds
+        );
+        holder_bonds.locked = holder_bonds.locked.checked_add(collateral_amount).unwrap();
+        loan.collateral = loan.collateral.checked_add(collateral_amount).unwrap();
+
+        loan.principal = loan.principal.checked_add(borrow_amount).unwrap();
+        require!(
+            loan.debt() <= loan.borrow_limit(bond.price, lending_pool.ltv_bps),
+            BondError::ExceedsLoanToValue
+        );
+        require!(
+            borrow_amount <= ctx.accounts.lending_vault.amount,
+            BondError::InsufficientLiquidity
+        );
+        lending_pool.total_borrowed = lending_pool.total_borrowed.checked_add(borrow_amount).unwrap();
+
+        if borrow_amount > 0 {
+            let bond_key = ctx.accounts.bond.key();
+            let lending_pool_seeds = &[
+                b"lending_pool".as_ref(),
+                bond_key.as_ref(),
+                &[ctx.accounts.lending_pool.bump]
+            ];
+            let signer = &[&lending_pool_seeds[..]];
+
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.lending_vault.to_account_info(),
+                to: ctx.accounts.borrower_token_account.to_account_info(),
+                authority: ctx.accounts.lending_pool.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token::transfer(cpi_ctx, borrow_amount)?;
+        }
+
+        emit!(LoanUpdatedEvent {
+            bond_id: ctx.accounts.bond.id,
+            borrower: ctx.accounts.loan.borrower,
+            collateral: ctx.accounts.loan.collateral,
+            debt: ctx.accounts.loan.debt(),
+        });
+
+        Ok(())
+    }
+
+    pub fn repay_loan(ctx: Context<ManageLoan>, amount: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let lending_pool = &mut ctx.accounts.lending_pool;
+        let loan = &mut ctx.accounts.loan;
+        lending_pool.accrue(now);
+        loan.accrue(lending_pool.interest_rate_bps, now);
+
+        let (interest_paid, principal_paid) = loan.apply_repayment(amount);
+        require!(interest_paid + principal_paid > 0, BondError::InvalidAmount);
+        lending_pool.book_repayment(interest_paid, principal_paid);
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.borrower_token_account.to_account_info(),
+            to: ctx.accounts.lending_vault.to_account_info(),
+            authority: ctx.accounts.borrower.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, interest_paid + principal_paid)?;
+
+        emit!(LoanUpdatedEvent {
+            bond_id: ctx.accounts.bond.id,
+            borrower: loan.borrower,
+            collateral: loan.collateral,
+            debt: loan.debt(),
+        });
+
+        Ok(())
+    }
+
+    pub fn withdraw_collateral(ctx: Context<ManageLoan>, amount: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let lending_pool = &mut ctx.accounts.lending_pool;
+        let loan = &mut ctx.accounts.loan;
+        require!(amount > 0 && amount <= loan.collateral, BondError::InvalidAmount);
+        lending_pool.accrue(now);
+        loan.accrue(lending_pool.interest_rate_bps, now);
+
+        loan.collateral -= amount;
+        require!(
+            loan.debt() <= loan.borrow_limit(ctx.accounts.bond.price, lending_pool.ltv_bps),
+            BondError::ExceedsLoanToValue
+        );
+
+        let holder_bonds = &mut ctx.accounts.holder_bonds;
+        holder_bonds.locked = holder_bonds.locked.checked_sub(amount).unwrap();
+
+        emit!(LoanUpdatedEvent {
+            bond_id: ctx.accounts.bond.id,
+            borrower: loan.borrower,
+            collateral: loan.collateral,
+            debt: loan.debt(),
+        });
+
+        Ok(())
+    }
+
+    pub fn liquidate_loan(ctx: Context<LiquidateLoan>, repay_amount: u64) -> Result<()> {
+        check_not_halted(&ctx.accounts.market)?;
+        let now = Clock::get()?.unix_timestamp;
+        let price = ctx.accounts.bond.price;
+        let lending_pool = &mut ctx.accounts.lending_pool;
+        let loan = &mut ctx.accounts.loan;
+        lending_pool.accrue(now);
+        loan.accrue(lending_pool.interest_rate_bps, now);
+
+        // Anyone can step in once the debt outgrows the liquidation threshold
+        let debt = loan.debt();
+        require!(
+            debt > loan.borrow_limit(price, lending_pool.liquidation_threshold_bps),
+            BondError::LoanHealthy
+        );
+
+        // Liquidations may be partial; the liquidator repays part of the debt and
+        // takes collateral worth that much plus a bonus
+        let repay_amount = repay_amount.min(debt);
+        require!(repay_amount > 0, BondError::InvalidAmount);
+        let collateral = loan.seizable_collateral(repay_amount, price);
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.liquidator_token_account.to_account_info(),
+            to: ctx.accounts.lending_vault.to_account_info(),
+            authority: ctx.accounts.liquidator.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, repay_amount)?;
+
+        let (interest_paid, principal_paid) = loan.apply_repayment(repay_amount);
+        lending_pool.book_repayment(interest_paid, principal_paid);
+        loan.collateral -= collateral;
+
+        // Debt left once the collateral is gone can't be recovered, so lenders absorb it
+        let mut bad_debt = 0;
+        if loan.collateral == 0 && loan.debt() > 0 {
+            bad_debt = loan.debt();
+            lending_pool.write_off(loan.interest, loan.principal);
+            loan.interest = 0;
+            loan.principal = 0;
+        }
+
+        let borrower_holder_bonds = &mut ctx.accounts.borrower_holder_bonds;
+        borrower_holder_bonds.locked = borrower_holder_bonds.locked.checked_sub(collateral).unwrap();
+
+        let liquidator_holder_bonds = &mut ctx.accounts.liquidator_holder_bonds;
+        liquidator_holder_bonds.holder = ctx.accounts.liquidator.key();
+        liquidator_holder_bonds.bond = ctx.accounts.bond.key();
+        liquidator_holder_bonds.bump = ctx.bumps.liquidator_holder_bonds;
+        ctx.accounts.bond.trade_limits.check_holding(
+            now,
+            liquidator_holder_bonds.amount.checked_add(collateral).unwrap(),
+        )?;
+
+        let market = &mut ctx.accounts.market;
+        debit_holder(
+            market,
+            borrower_holder_bonds,
+            &ctx.accounts.borrower_holder_page.page,
+            collateral,
+        )?;
+        credit_holder(
+            market,
+            liquidator_holder_bonds,
+            &ctx.accounts.liquidator_holder_page.page,
+            &ctx.accounts.liquidator,
+            &ctx.accounts.system_program,
+            collateral,
+        )?;
+
+        emit!(LoanLiquidatedEvent {
+            bond_id: ctx.accounts.bond.id,
+            borrower: loan.borrower,
+            liquidator: ctx.accounts.liquidator.key(),
+            collateral,
+            repaid: repay_amount,
+            bad_debt,
+        });
+
+        Ok(())
+    }
+
+    pub fn create_staking_pool(
+        ctx: Context<CreateStakingPool>,
+        unstake_cooldown: i64,
+        reward_duration: i64,
+    ) -> Result<()> {
+        require!(unstake_cooldown >= 0, BondError::InvalidCooldown);
+        require!(reward_duration > 0, BondError::InvalidRewardDuration);
+
+        let staking_pool = &mut ctx.accounts.staking_pool;
+        staking_pool.bond = ctx.accounts.bond.key();
+        staking_pool.reward_mint = ctx.accounts.reward_mint.key();
+        staking_pool.reward_vault = ctx.accounts.reward_vault.key();
+        staking_pool.total_staked = 0;
+        staking_pool.acc_reward_per_share = 0;
+        staking_pool.reward_rate = 0;
+        staking_pool.reward_duration = reward_duration;
+        staking_pool.reward_end = 0;
+        staking_pool.last_update = Clock::get()?.unix_timestamp;
+        staking_pool.unstake_cooldown = unstake_cooldown;
+        staking_pool.bump = ctx.bumps.staking_pool;
+
+        Ok(())
+    }
+
+    pub fn fund_staking_rewards(ctx: Context<FundStakingRewards>, amount: u64) -> Result<()> {
+        require!(amount > 0, BondError::InvalidAmount);
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.funder_token_account.to_account_info(),
+            to: ctx.accounts.reward_vault.to_account_info(),
+            authority: ctx.accounts.funder.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, amount)?;
+
+        let staking_pool = &mut ctx.accounts.staking_pool;
+        staking_pool.fund(amount, Clock::get()?.unix_timestamp);
+
+        emit!(StakingRewardsFundedEvent {
+            bond_id: ctx.accounts.bond.id,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn stake(ctx: Context<StakeBonds>, amount: u64) -> Result<()> {
+        require!(amount > 0, BondError::InvalidAmount);
+
+        let holder_bonds = &mut ctx.accounts.holder_bonds;
+        require!(amount <= holder_bonds.available(), BondError::InsufficientBonds);
+        holder_bonds.locked = holder_bonds.locked.checked_add(amount).unwrap();
+
+        let staking_pool = &mut ctx.accounts.staking_pool;
+        staking_pool.update_rewards(Clock::get()?.unix_timestamp);
+        let stake_position = &mut ctx.accounts.stake_position;
+        stake_position.staker = ctx.accounts.staker.key();
+        stake_position.staking_pool = staking_pool.key();
+        stake_position.bump = ctx.bumps.stake_position;
+        stake_position.harvest(staking_pool.acc_reward_per_share);
+
+        stake_position.amount = stake_position.amount.checked_add(amount).unwrap();
+        stake_position.sync(staking_pool.acc_reward_per_share);
+        staking_pool.total_staked = staking_pool.total_staked.checked_add(amount).unwrap();
+
+        emit!(StakeUpdatedEvent {
+            bond_id: ctx.accounts.bond.id,
+            staker: stake_position.staker,
+            staked: stake_position.amount,
+            unstaking: stake_position.unstaking_amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn unstake(ctx: Context<StakeBonds>, amount: u64) -> Result<()> {
+        let staking_pool = &mut ctx.accounts.staking_pool;
+        let stake_position = &mut ctx.accounts.stake_position;
+        require!(
+            amount > 0 && amount <= stake_position.amount,
+            BondError::InvalidAmount
+        );
+        staking_pool.update_rewards(Clock::get()?.unix_timestamp);
+        stake_position.harvest(staking_pool.acc_reward_per_share);
+
+        // Unstaked units stop earning right away but stay locked through the cooldown
+        stake_position.amount -= amount;
+        stake_position.sync(staking_pool.acc_reward_per_share);
+        staking_pool.total_staked = staking_pool.total_staked.checked_sub(amount).unwrap();
+
+        stake_position.unstaking_amount = stake_position.unstaking_amount.checked_add(amount).unwrap();
+        stake_position.unstake_available_at = Clock::get()?
+            .unix_timestamp
+            .checked_add(staking_pool.unstake_cooldown)
+            .unwrap();
+
+        emit!(StakeUpdatedEvent {
+            bond_id: ctx.accounts.bond.id,
+            staker: stake_position.staker,
+            staked: stake_position.amount,
+            unstaking: stake_position.unstaking_amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn withdraw_unstaked(ctx: Context<StakeBonds>) -> Result<()> {
+        let stake_position = &mut ctx.accounts.stake_position;
+        let amount = stake_position.unstaking_amount;
+        require!(amount > 0, BondError::InvalidAmount);
+        require!(
+            Clock::get()?.unix_timestamp >= stake_position.unstake_available_at,
+            BondError::CooldownActive
+        );
+
+        stake_position.unstaking_amount = 0;
+        let holder_bonds = &mut ctx.accounts.holder_bonds;
+        holder_bonds.locked = holder_bonds.locked.checked_sub(amount).unwrap();
+
+        emit!(StakeUpdatedEvent {
+            bond_id: ctx.accounts.bond.id,
+            staker: stake_position.staker,
+            staked: stake_position.amount,
+            unstaking: 0,
+        });
+
+        Ok(())
+    }
+
+    pub fn claim_staking_rewards(ctx: Context<ClaimStakingRewards>) -> Result<()> {
+        let staking_pool = &mut ctx.accounts.staking_pool;
+        staking_pool.update_rewards(Clock::get()?.unix_timestamp);
+        let stake_position = &mut ctx.accounts.stake_position;
+        stake_position.harvest(staking_pool.acc_reward_per_share);
+        stake_position.sync(staking_pool.acc_reward_per_share);
+
+        let amount = std::mem::take(&mut stake_position.pending_rewards);
+        require!(amount > 0, BondError::NothingToClaim);
+
+        let bond_key = ctx.accounts.bond.key();
+        let staking_pool_seeds = &[
+            b"staking_pool".as_ref(),
+            bond_key.as_ref(),
+            &[staking_pool.bump]
+        ];
+        let signer = &[&staking_pool_seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.reward_vault.to_account_info(),
+            to: ctx.accounts.staker_token_account.to_account_info(),
+            authority: staking_pool.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::transfer(cpi_ctx, amount)?;
+
+        emit!(StakingRewardsClaimedEvent {
+            bond_id: ctx.accounts.bond.id,
+            staker: stake_position.staker,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn create_prediction_market(
+        ctx: Context<CreatePredictionMarket>,
+        threshold: u64,
+        resolve_time: i64,
+    ) -> Result<()> {
+        require!(
+            resolve_time > Clock::get()?.unix_timestamp,
+            BondError::InvalidResolveTime
+        );
+
+        // Composite bonds report a COMPOSITE_INDEX_SCALE index rather than a raw metric,
+        // so a raw threshold would never compare meaningfully against it
+        let bond_key = ctx.accounts.bond.key();
+        let (composite_metric, _) =
+            Pubkey::find_program_address(&[b"composite", bond_key.as_ref()], &crate::ID);
+        require_keys_neq!(
+            ctx.accounts.bond.oracle,
+            composite_metric,
+            BondError::CompositeBondUnsupported
+        );
+
+        let prediction_market = &mut ctx.accounts.prediction_market;
+        prediction_market.bond = ctx.accounts.bond.key();
+        prediction_market.creator = ctx.accounts.creator.key();
+        prediction_market.collateral_mint = ctx.accounts.collateral_mint.key();
+        prediction_market.collateral_vault = ctx.accounts.collateral_vault.key();
+        prediction_market.yes_mint = ctx.accounts.yes_mint.key();
+        prediction_market.no_mint = ctx.accounts.no_mint.key();
+        prediction_market.threshold = threshold;
+        prediction_market.resolve_time = resolve_time;
+        prediction_market.outstanding_sets = 0;
+        prediction_market.resolution_value = 0;
+        prediction_market.value_recorded = false;
+        prediction_market.resolved = false;
+        prediction_market.outcome_yes = false;
+        prediction_market.bump = ctx.bumps.prediction_market;
+
+        emit!(PredictionMarketCreatedEvent {
+            bond_id: ctx.accounts.bond.id,
+            prediction_market: prediction_market.key(),
+            threshold,
+            resolve_time,
+        });
+
+        Ok(())
+    }
+
+    pub fn mint_prediction_shares(ctx: Context<PredictionShares>, amount: u64) -> Result<()> {
+        require!(amount > 0, BondError::InvalidAmount);
+        require!(
+            Clock::get()?.unix_timestamp < ctx.accounts.prediction_market.resolve_time,
+            BondError::PredictionMarketClosed
+        );
+
+        // Each unit of collateral backs one YES and one NO share
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.user_collateral_account.to_account_info(),
+            to: ctx.accounts.collateral_vault.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, amount)?;
+
+        let prediction_market = &ctx.accounts.prediction_market;
+        let bond_key = prediction_market.bond;
+        let threshold_bytes = prediction_market.threshold.to_le_bytes();
+        let resolve_time_bytes = prediction_market.resolve_time.to_le_bytes();
+        let prediction_seeds = &[
+            b"prediction".as_ref(),
+            bond_key.as_ref(),
+            threshold_bytes.as_ref(),
+            resolve_time_bytes.as_ref(),
+            &[prediction_market.bump]
+        ];
+        let signer = &[&prediction_seeds[..]];
+
+        let cpi_accounts = MintTo {
+            mint: ctx.accounts.yes_mint.to_account_info(),
+            to: ctx.accounts.user_yes_account.to_account_info(),
+            authority: ctx.accounts.prediction_market.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::mint_to(cpi_ctx, amount)?;
+
+        let cpi_accounts = MintTo {
+            mint: ctx.accounts.no_mint.to_account_info(),
+            to: ctx.accounts.user_no_account.to_account_info(),
+            authority: ctx.accounts.prediction_market.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::mint_to(cpi_ctx, amount)?;
+
+        let prediction_market = &mut ctx.accounts.prediction_market;
+        prediction_market.outstanding_sets = prediction_market.outstanding_sets.checked_add(amount).unwrap();
+
+        Ok(())
+    }
+
+    pub fn redeem_prediction_shares(ctx: Context<PredictionShares>, amount: u64) -> Result<()> {
+        require!(amount > 0, BondError::InvalidAmount);
+
+        // A complete YES + NO set is always worth one unit of collateral
+        let cpi_accounts = Burn {
+            mint: ctx.accounts.yes_mint.to_account_info(),
+            from: ctx.accounts.user_yes_account.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::burn(cpi_ctx, amount)?;
+
+        let cpi_accounts = Burn {
+            mint: ctx.accounts.no_mint.to_account_info(),
+            from: ctx.accounts.user_no_account.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::burn(cpi_ctx, amount)?;
+
+        let prediction_market = &ctx.accounts.prediction_market;
+        let bond_key = prediction_market.bond;
+        let threshold_bytes = prediction_market.threshold.to_le_bytes();
+        let resolve_time_bytes = prediction_market.resolve_time.to_le_bytes();
+        let prediction_seeds = &[
+            b"prediction".as_ref(),
+            bond_key.as_ref(),
+            threshold_bytes.as_ref(),
+            resolve_time_bytes.as_ref(),
+            &[prediction_market.bump]
+        ];
+        let signer = &[&prediction_seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.collateral_vault.to_account_info(),
+            to: ctx.accounts.user_collateral_account.to_account_info(),
+            authority: ctx.accounts.prediction_market.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::transfer(cpi_ctx, amount)?;
+
+        let prediction_market = &mut ctx.accounts.prediction_market;
+        prediction_market.outstanding_sets = prediction_market.outstanding_sets.checked_sub(amount).unwrap();
+
+        Ok(())
+    }
+
+    pub fn resolve_prediction_market(ctx: Context<ResolvePredictionMarket>) -> Result<()> {
+        let bond = &ctx.accounts.bond;
+        let prediction_market = &mut ctx.accounts.prediction_market;
+        require!(!prediction_market.resolved, BondError::PredictionMarketResolved);
+        let resolve_time = prediction_market.resolve_time;
+        require!(
+            Clock::get()?.unix_timestamp > resolve_time,
+            BondError::PredictionMarketNotResolvable
+        );
+
+        // Settle on the metric value in effect at the resolve time. If update_metric didn't
+        // record it, it is still the current reading when no update has landed since, and
+        // otherwise it is read back from the bond's metric history.
+        if !prediction_market.value_recorded {
+            prediction_market.resolution_value = if bond.last_update <= resolve_time {
+                bond.current_value
+            } else {
+                let metric_history = ctx
+                    .accounts
+                    .metric_history
+                    .as_ref()
+                    .ok_or(BondError::PredictionMarketNotResolvable)?;
+                let account_info = metric_history.as_ref();
+                let data = account_info.try_borrow_data()?;
+                let (header, samples) = split_metric_history(&data);
+                header
+                    .value_at(samples, resolve_time)
+                    .ok_or(BondError::PredictionMarketNotResolvable)?
+            };
+            prediction_market.value_recorded = true;
+        }
+
+        prediction_market.resolved = true;
+        prediction_market.outcome_yes =
+            prediction_market.resolution_value > prediction_market.threshold;
+
+        emit!(PredictionMarketResolvedEvent {
+            bond_id: bond.id,
+            prediction_market: prediction_market.key(),
+            metric_value: prediction_market.resolution_value,
+            outcome_yes: prediction_market.outcome_yes,
+        });
+
+        Ok(())
+    }
+
+    pub fn claim_prediction_payout(ctx: Context<ClaimPredictionPayout>, amount: u64) -> Result<()> {
+        require!(amount > 0, BondError::InvalidAmount);
+
+        let prediction_market = &ctx.accounts.prediction_market;
+        require!(prediction_market.resolved, BondError::PredictionMarketNotResolved);
+        let winning_mint = if prediction_market.outcome_yes {
+            prediction_market.yes_mint
+        } else {
+            prediction_market.no_mint
+        };
+        require_keys_eq!(
+            ctx.accounts.winning_mint.key(),
+            winning_mint,
+            BondError::InvalidPredictionMint
+        );
+
+        let cpi_accounts = Burn {
+            mint: ctx.accounts.winning_mint.to_account_info(),
+            from: ctx.accounts.user_winning_account.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::burn(cpi_ctx, amount)?;
+
+        let prediction_market = &ctx.accounts.prediction_market;
+        let bond_key = prediction_market.bond;
+        let threshold_bytes = prediction_market.threshold.to_le_bytes();
+        let resolve_time_bytes = prediction_market.resolve_time.to_le_bytes();
+        let prediction_seeds = &[
+            b"prediction".as_ref(),
+            bond_key.as_ref(),
+            threshold_bytes.as_ref(),
+            resolve_time_bytes.as_ref(),
+            &[prediction_market.bump]
+        ];
+        let signer = &[&prediction_seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.collateral_vault.to_account_info(),
+            to: ctx.accounts.user_collateral_account.to_account_info(),
+            authority: ctx.accounts.prediction_market.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::transfer(cpi_ctx, amount)?;
+
+        emit!(PredictionPayoutClaimedEvent {
+            prediction_market: prediction_market.key(),
+            user: ctx.accounts.user.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn create_perp_market(
+        ctx: Context<CreatePerpMarket>,
+        max_leverage: u8,
+        maintenance_margin_bps: u16,
+        funding_rate_bps: u16,
+    ) -> Result<()> {
+        require!(
+            max_leverage > 0 && max_leverage <= MAX_PERP_LEVERAGE,
+            BondError::InvalidPerpParams
+        );
+
+        // A max-leverage position must not be liquidatable the moment it opens
+        require!(
+            maintenance_margin_bps > 0
+                && (maintenance_margin_bps as u64) * (max_leverage as u64) < 10_000,
+            BondError::InvalidPerpParams
+        );
+
+        let perp_market = &mut ctx.accounts.perp_market;
+        perp_market.bond = ctx.accounts.bond.key();
+        perp_market.collateral_mint = ctx.accounts.collateral_mint.key();
+        perp_market.collateral_vault = ctx.accounts.collateral_vault.key();
+        perp_market.max_leverage = max_leverage;
+        perp_market.maintenance_margin_bps = maintenance_margin_bps;
+        perp_market.funding_rate_bps = funding_rate_bps;
+        perp_market.long_open_interest = 0;
+        perp_market.short_open_interest = 0;
+        perp_market.cumulative_funding = 0;
+        perp_market.last_funding_time = Clock::get()?.unix_timestamp;
+        perp_market.lp_capital = 0;
+        perp_market.lp_shares = 0;
+        perp_market.total_margin = 0;
+        perp_market.realized_pnl = 0;
+        perp_market.bad_debt = 0;
+        perp_market.bump = ctx.bumps.perp_market;
+
+        Ok(())
+    }
+
+    pub fn deposit_perp_liquidity(ctx: Context<PerpLiquidity>, amount: u64) -> Result<()> {
+        require!(amount > 0, BondError::InvalidAmount);
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.provider_token_account.to_account_info(),
+            to: ctx.accounts.collateral_vault.to_account_info(),
+            authority: ctx.accounts.provider.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, amount)?;
+
+        let perp_market = &mut ctx.accounts.perp_market;
+        let shares = perp_market.shares_for(amount);
+        require!(shares > 0, BondError::InvalidAmount);
+        perp_market.lp_capital = perp_market.lp_capital.checked_add(amount).unwrap();
+        perp_market.lp_shares = perp_market.lp_shares.checked_add(shares).unwrap();
+
+        let perp_lp_position = &mut ctx.accounts.perp_lp_position;
+        perp_lp_position.provider = ctx.accounts.provider.key();
+        perp_lp_position.perp_market = perp_market.key();
+        perp_lp_position.shares = perp_lp_position.shares.checked_add(shares).unwrap();
+        perp_lp_position.bump = ctx.bumps.perp_lp_position;
+
+        Ok(())
+    }
+
+    pub fn withdraw_perp_liquidity(ctx: Context<PerpLiquidity>, shares: u64) -> Result<()> {
+        let perp_lp_position = &mut ctx.accounts.perp_lp_position;
+        require!(
+            shares > 0 && shares <= perp_lp_position.shares,
+            BondError::InvalidAmount
+        );
+
+        let perp_market = &mut ctx.accounts.perp_market;
+        let amount = perp_market.value_of(shares);
+        perp_lp_position.shares -= shares;
+        perp_market.lp_shares = perp_market.lp_shares.checked_sub(shares).unwrap();
+        perp_market.lp_capital = perp_market.lp_capital.checked_sub(amount).unwrap();
+
+        // Capital backing open positions can't leave
+        require!(
+            perp_market.net_exposure(ctx.accounts.bond.price) <= perp_market.lp_capital as u128,
+            BondError::PerpOpenInterestCapExceeded
+        );
+
+        let bond_key = ctx.accounts.bond.key();
+        let perp_market_seeds = &[
+            b"perp_market".as_ref(),
+            bond_key.as_ref(),
+            &[ctx.accounts.perp_market.bump]
+        ];
+        let signer = &[&perp_market_seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.collateral_vault.to_account_info(),
+            to: ctx.accounts.provider_token_account.to_account_info(),
+            authority: ctx.accounts.perp_market.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::transfer(cpi_ctx, amount)?;
+
+        Ok(())
+    }
+
+    pub fn open_perp_position(
+        ctx: Context<OpenPerpPosition>,
+        is_long: bool,
+        size: u64,
+        margin: u64,
+    ) -> Result<()> {
+        let bond = &ctx.accounts.bond;
+        check_trading_allowed(bond, &ctx.accounts.market)?;
+        require!(size > 0 && margin > 0, BondError::InvalidAmount);
+
+        let perp_position = &mut ctx.accounts.perp_position;
+        require!(perp_position.size == 0, BondError::PerpPositionOpen);
+
+        let perp_market = &mut ctx.accounts.perp_market;
+        perp_market.update_funding(bond.price, Clock::get()?.unix_timestamp);
+
+        let notional = size.checked_mul(bond.price).unwrap();
+        require!(
+            notional <= margin.checked_mul(perp_market.max_leverage as u64).unwrap(),
+            BondError::ExceedsMaxLeverage
+        );
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.trader_token_account.to_account_info(),
+            to: ctx.accounts.collateral_vault.to_account_info(),
+            authority: ctx.accounts.trader.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, margin)?;
+
+        perp_position.trader = ctx.accounts.trader.key();
+        perp_position.perp_market = perp_market.key();
+        perp_position.is_long = is_long;
+        perp_position.size = size;
+        perp_position.entry_price = bond.price;
+        perp_position.margin = margin;
+        perp_position.funding_snapshot = perp_market.cumulative_funding;
+        perp_position.bump = ctx.bumps.perp_position;
+
+        // LP capital is the counterparty to every position, so the net exposure it
+        // backs can't outgrow it, unless this position reduces that exposure
+        let exposure_before = perp_market.net_exposure(bond.price);
+        perp_market.add_open_interest(is_long, size);
+        let exposure_after = perp_market.net_exposure(bond.price);
+        require!(
+            exposure_after <= perp_market.lp_capital as u128 || exposure_after < exposure_before,
+            BondError::PerpOpenInterestCapExceeded
+        );
+        perp_market.total_margin = perp_market.total_margin.checked_add(margin).unwrap();
+
+        emit!(PerpPositionOpenedEvent {
+            bond_id: bond.id,
+            trader: perp_position.trader,
+            is_long,
+            size,
+            entry_price: bond.price,
+            margin,
+        });
+
+        Ok(())
+    }
+
+    pub fn close_perp_position(ctx: Context<ClosePerpPosition>) -> Result<()> {
+        check_not_halted(&ctx.accounts.market)?;
+        let mark_price = ctx.accounts.bond.price;
+        let perp_market = &mut ctx.accounts.perp_market;
+        perp_market.update_funding(mark_price, Clock::get()?.unix_timestamp);
+
+        let perp_position = &mut ctx.accounts.perp_position;
+        require!(perp_position.size > 0, BondError::NoPerpPosition);
+
+        // Positions settle against LP capital; a profit it can't cover fails the close
+        // rather than being paid out of other traders' margin
+        let equity = perp_position.equity(mark_price, perp_market.cumulative_funding);
+        let payout = equity.max(0) as u64;
+        let realized_pnl = equity - perp_position.margin as i128;
+        perp_market.settle_position(perp_position.margin, equity, payout)?;
+
+        perp_market.remove_open_interest(perp_position.is_long, perp_position.size);
+        perp_position.size = 0;
+        perp_position.margin = 0;
+
+        if payout > 0 {
+            let bond_key = ctx.accounts.bond.key();
+            let perp_market_seeds = &[
+                b"perp_market".as_ref(),
+                bond_key.as_ref(),
+                &[ctx.accounts.perp_market.bump]
+            ];
+            let signer = &[&perp_market_seeds[..]];
+
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.collateral_vault.to_account_info(),
+                to: ctx.accounts.trader_token_account.to_account_info(),
+                authority: ctx.accounts.perp_market.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token::transfer(cpi_ctx, payout)?;
+        }
+
+        emit!(PerpPositionClosedEvent {
+            bond_id: ctx.accounts.bond.id,
+            trader: ctx.accounts.perp_position.trader,
+            exit_price: mark_price,
+            realized_pnl: realized_pnl as i64,
+            payout,
+        });
+
+        Ok(())
+    }
+
+    pub fn liquidate_perp_position(ctx: Context<LiquidatePerpPosition>) -> Result<()> {
+        let mark_price = ctx.accounts.bond.price;
+        let perp_market = &mut ctx.accounts.perp_market;
+        perp_market.update_funding(mark_price, Clock::get()?.unix_timestamp);
+
+        let perp_position = &mut ctx.accounts.perp_position;
+        require!(perp_position.size > 0, BondError::NoPerpPosition);
+
+        let notional = perp_position.size.checked_mul(mark_price).unwrap() as i128;
+        let maintenance_margin = notional * perp_market.maintenance_margin_bps as i128 / 10_000;
+        let equity = perp_position.equity(mark_price, perp_market.cumulative_funding);
+        require!(equity < maintenance_margin, BondError::PerpPositionHealthy);
+
+        // The liquidator earns a fee out of whatever equity is left and the rest goes to
+        // LP capital; a negative equity is recorded as bad debt
+        let liquidation_fee = (notional * PERP_LIQUIDATION_FEE_BPS as i128 / 10_000)
+            .min(equity.max(0)) as u64;
+        perp_market.settle_position(perp_position.margin, equity, liquidation_fee)?;
+
+        perp_market.remove_open_interest(perp_position.is_long, perp_position.size);
+        let size = perp_position.size;
+        perp_position.size = 0;
+        perp_position.margin = 0;
+
+        if liquidation_fee > 0 {
+            let bond_key = ctx.accounts.bond.key();
+            let perp_market_seeds = &[
+                b"perp_market".as_ref(),
+                bond_key.as_ref(),
+                &[ctx.accounts.perp_market.bump]
+            ];
+            let signer = &[&perp_market_seeds[..]];
+
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.collateral_vault.to_account_info(),
+                to: ctx.accounts.liquidator_token_account.to_account_info(),
+                authority: ctx.accounts.perp_market.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token::transfer(cpi_ctx, liquidation_fee)?;
+        }
+
+        emit!(PerpPositionLiquidatedEvent {
+            bond_id: ctx.accounts.bond.id,
+            trader: ctx.accounts.perp_position.trader,
+            liquidator: ctx.accounts.liquidator.key(),
+            size,
+            mark_price,
+            liquidation_fee,
+        });
+
+        Ok(())
+    }
+
+    pub fn create_basket(
+        ctx: Context<CreateBasket>,
+        basket_id: u64,
+        constituents: Vec<BasketConstituent>,
+    ) -> Result<()> {
+        validate_basket_constituents(&constituents, &[])?;
+
+        let basket = &mut ctx.accounts.basket;
+        basket.manager = ctx.accounts.manager.key();
+        basket.basket_id = basket_id;
+        basket.share_mint = ctx.accounts.share_mint.key();
+        basket.constituents = constituents;
+        basket.bump = ctx.bumps.basket;
+
+        Ok(())
+    }
+
+    pub fn rebalance_basket<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RebalanceBasket<'info>>,
+        constituents: Vec<BasketConstituent>,
+    ) -> Result<()> {
+        let basket = &mut ctx.accounts.basket;
+        validate_basket_constituents(&constituents, &basket.constituents)?;
+        basket.constituents = constituents;
+
+        // The manager is the counterparty: overweight holdings go to them and underweight
+        // ones are bought in from them at current prices. Targets round up, so the basket
+        // never gives away more value than it takes in.
+        let manager = ctx.accounts.manager.to_account_info();
+        let system_program = ctx.accounts.system_program.to_account_info();
+        let mut legs = load_basket_legs(basket, &manager.key(), ctx.remaining_accounts)?;
+        let nav = basket_legs_nav(&legs)?;
+        let mut value_in: u64 = 0;
+        let mut value_out: u64 = 0;
+        for (constituent, leg) in basket.constituents.iter().zip(legs.iter_mut()) {
+            require!(leg.bond.price > 0, BondError::InvalidPrice);
+            let target = (nav as u128 * constituent.weight_bps as u128)
+                .div_ceil(10_000 * leg.bond.price as u128) as u64;
+            let holdings = leg.basket_holdings;
+            if holdings > target {
+                leg.transfer(true, holdings - target, &manager, &system_program)?;
+                value_out = value_out.checked_add((holdings - target).checked_mul(leg.bond.price).unwrap()).unwrap();
+            } else if target > holdings {
+                leg.transfer(false, target - holdings, &manager, &system_program)?;
+                value_in = value_in.checked_add((target - holdings).checked_mul(leg.bond.price).unwrap()).unwrap();
+            }
+        }
+        require!(value_in >= value_out, BondError::InvalidBasketConstituents);
+
+        emit!(BasketRebalancedEvent {
+            basket: basket.key(),
+            constituents: basket.constituents.clone(),
+            value_in,
+            value_out,
+        });
+
+        Ok(())
+    }
+
+    pub fn mint_basket_shares<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BasketShares<'info>>,
+        value: u64,
+    ) -> Result<()> {
+        require!(value > 0, BondError::InvalidAmount);
+
+        let basket = &ctx.accounts.basket;
+        let user = ctx.accounts.user.to_account_info();
+        let system_program = ctx.accounts.system_program.to_account_info();
+        let mut legs = load_basket_legs(basket, &user.key(), ctx.remaining_accounts)?;
+        let nav = basket_legs_nav(&legs)?;
+
+        // Deposits come in as the weighted mix of every constituent, valued at current prices
+        let mut deposit_value: u64 = 0;
+        for (constituent, leg) in basket.constituents.iter().zip(legs.iter_mut()) {
+            if constituent.weight_bps == 0 {
+                continue;
+            }
+            require!(leg.bond.price > 0, BondError::InvalidPrice);
+            let amount = (value as u128 * constituent.weight_bps as u128
+                / (10_000 * leg.bond.price as u128)) as u64;
+            require!(amount > 0, BondError::InvalidAmount);
+            leg.transfer(false, amount, &user, &system_program)?;
+            deposit_value = deposit_value.checked_add(amount.checked_mul(leg.bond.price).unwrap()).unwrap();
+        }
+
+        let share_supply = ctx.accounts.share_mint.supply;
+        let shares = if share_supply == 0 || nav == 0 {
+            deposit_value
+        } else {
+            (deposit_value as u128 * share_supply as u128 / nav as u128) as u64
+        };
+        require!(shares > 0, BondError::InvalidAmount);
+
+        let manager_key = basket.manager;
+        let basket_id_bytes = basket.basket_id.to_le_bytes();
+        let basket_seeds = &[
+            b"basket".as_ref(),
+            manager_key.as_ref(),
+            basket_id_bytes.as_ref(),
+            &[basket.bump]
+        ];
+        let signer = &[&basket_seeds[..]];
+
+        let cpi_accounts = MintTo {
+            mint: ctx.accounts.share_mint.to_account_info(),
+            to: ctx.accounts.user_share_account.to_account_info(),
+            authority: ctx.accounts.basket.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::mint_to(cpi_ctx, shares)?;
+
+        emit!(BasketSharesMintedEvent {
+            basket: ctx.accounts.basket.key(),
+            user: ctx.accounts.user.key(),
+            value: deposit_value,
+            shares,
+        });
+
+        Ok(())
+    }
+
+    pub fn redeem_basket_shares<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BasketShares<'info>>,
+        shares: u64,
+    ) -> Result<()> {
+        let share_supply = ctx.accounts.share_mint.supply;
+        require!(shares > 0 && shares <= share_supply, BondError::InvalidAmount);
+
+        let basket = &ctx.accounts.basket;
+        let user = ctx.accounts.user.to_account_info();
+        let system_program = ctx.accounts.system_program.to_account_info();
+        let mut legs = load_basket_legs(basket, &user.key(), ctx.remaining_accounts)?;
+
+        // Shares redeem for their pro rata cut of every holding, zero-weighted ones included
+        let mut redeem_value: u64 = 0;
+        for leg in legs.iter_mut() {
+            let amount = (leg.basket_holdings as u128 * shares as u128 / share_supply as u128) as u64;
+            if amount > 0 {
+                leg.transfer(true, amount, &user, &system_program)?;
+                redeem_value = redeem_value.checked_add(amount.checked_mul(leg.bond.price).unwrap()).unwrap();
+            }
+        }
+        require!(redeem_value > 0, BondError::InvalidAmount);
+
+        let cpi_accounts = Burn {
+            mint: ctx.accounts.share_mint.to_account_info(),
+            from: ctx.accounts.user_share_account.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::burn(cpi_ctx, shares)?;
+
+        emit!(BasketSharesRedeemedEvent {
+            basket: ctx.accounts.basket.key(),
+            user: ctx.accounts.user.key(),
+            value: redeem_value,
+            shares,
+        });
+
+        Ok(())
+    }
+
+    pub fn configure_composite_metric(
+        ctx: Context<ConfigureCompositeMetric>,
+        components: Vec<MetricComponentConfig>,
+    ) -> Result<()> {
+        require!(
+            !components.is_empty() && components.len() <= MAX_METRIC_COMPONENTS,
+            BondError::InvalidMetricComponents
+        );
+
+        let mut total_weight: u32 = 0;
+        for (index, component) in components.iter().enumerate() {
+            require!(component.name.len() <= MAX_BOND_METRIC_LEN, BondError::MetricTooLong);
+
+            // Readings are matched to components by oracle, so each needs its own
+            require!(
+                components[..index].iter().all(|other| other.oracle != component.oracle),
+                BondError::InvalidMetricComponents
+            );
+            total_weight += component.weight_bps as u32;
+        }
+        require!(total_weight == 10_000, BondError::InvalidMetricComponents);
+
+        let composite_metric = &mut ctx.accounts.composite_metric;
+        composite_metric.bond = ctx.accounts.bond.key();
+        composite_metric.components = components
+            .into_iter()
+            .map(|component| MetricComponent {
+                name: component.name,
+                oracle: component.oracle,
+                weight_bps: component.weight_bps,
+                base_value: 0,
+                current_value: 0,
+                last_update: 0,
+            })
+            .collect();
+        composite_metric.bump = ctx.bumps.composite_metric;
+
+        // The composite account becomes the bond's oracle and the index starts at par
+        let bond = &mut ctx.accounts.bond;
+        bond.oracle = composite_metric.key();
+        bond.current_value = COMPOSITE_INDEX_SCALE;
+
+        Ok(())
+    }
+
+    pub fn create_metric_history(
+        ctx: Context<CreateMetricHistory>,
+        capacity: u32,
+        min_interval: i64,
+    ) -> Result<()> {
+        require!(
+            capacity > 0 && capacity <= MAX_METRIC_HISTORY_CAPACITY,
+            BondError::InvalidHistoryCapacity
+        );
+        require!(min_interval >= 0, BondError::InvalidHistoryInterval);
+
+        let mut metric_history = ctx.accounts.metric_history.load_init()?;
+        metric_history.bond = ctx.accounts.bond.key();
+        metric_history.min_interval = min_interval;
+        metric_history.last_sample_time = 0;
+        metric_history.capacity = capacity;
+        metric_history.len = 0;
+        metric_history.head = 0;
+        metric_history.bump = ctx.bumps.metric_history;
+
+        ctx.accounts.market.has_metric_history = true;
+        Ok(())
+    }
+
+    // Read-only; returns the metric value in effect at `timestamp` as instruction return data
+    pub fn metric_value_at(ctx: Context<ReadMetricHistory>, timestamp: i64) -> Result<u64> {
+        let account_info = ctx.accounts.metric_history.as_ref();
+        let data = account_info.try_borrow_data()?;
+        let (header, samples) = split_metric_history(&data);
+        header
+            .value_at(samples, timestamp)
+            .ok_or(BondError::MetricHistoryUnavailable.into())
+    }
+
+    pub fn create_proposal(ctx: Context<CreateProposal>, action: ProposalAction) -> Result<()> {
+        // Only one proposal runs at a time, so proposers need a real stake to take the slot
+        let bond = &ctx.accounts.bond;
+        let circulating_supply = bond.total_supply.checked_sub(bond.current_supply).unwrap();
+        let proposal_threshold =
+            (circulating_supply.checked_mul(GOVERNANCE_PROPOSAL_THRESHOLD_BPS).unwrap() / 10_000).max(1);
+        require!(
+            ctx.accounts.proposer_holder_bonds.amount >= proposal_threshold,
+            BondError::InsufficientBonds
+        );
+        validate_proposal_action(&action, ctx.accounts.pool.as_deref())?;
+
+        // Opening a proposal starts a new snapshot epoch for holder balances
+        let market = &mut ctx.accounts.market;
+        require!(!market.proposal_active, BondError::ProposalActive);
+        market.governance_epoch = market.governance_epoch.checked_add(1).unwrap();
+        market.proposal_active = true;
+
+        let now = Clock::get()?.unix_timestamp;
+
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.bond = bond.key();
+        proposal.proposer = ctx.accounts.proposer.key();
+        proposal.epoch = market.governance_epoch;
+        proposal.action = action;
+        proposal.end_time = now.checked_add(GOVERNANCE_VOTING_PERIOD).unwrap();
+        proposal.yes_votes = 0;
+        proposal.no_votes = 0;
+        proposal.quorum_votes = circulating_supply.checked_mul(GOVERNANCE_QUORUM_BPS).unwrap() / 10_000;
+        proposal.executed = false;
+        proposal.bump = ctx.bumps.proposal;
+
+        emit!(ProposalCreatedEvent {
+            bond_id: bond.id,
+            epoch: proposal.epoch,
+            proposer: proposal.proposer,
+            action,
+            end_time: proposal.end_time,
+        });
+
+        Ok(())
+    }
+
+    pub fn cast_vote(ctx: Context<CastVote>, support: bool) -> Result<()> {
+        let proposal = &mut ctx.accounts.proposal;
+        require!(
+            Clock::get()?.unix_timestamp < proposal.end_time,
+            BondError::VotingClosed
+        );
+
+        let weight = ctx.accounts.voter_holder_bonds.voting_weight(proposal.epoch);
+        require!(weight > 0, BondError::InsufficientBonds);
+
+        if support {
+            proposal.yes_votes = proposal.yes_votes.checked_add(weight).unwrap();
+        } else {
+            proposal.no_votes = proposal.no_votes.checked_add(weight).unwrap();
+        }
+
+        let vote_record = &mut ctx.accounts.vote_record;
+        vote_record.proposal = proposal.key();
+        vote_record.voter = ctx.accounts.voter.key();
+        vote_record.weight = weight;
+        vote_record.support = support;
+        vote_record.bump = ctx.bumps.vote_record;
+
+        emit!(VoteCastEvent {
+            bond_id: ctx.accounts.bond.id,
+            epoch: proposal.epoch,
+            voter: vote_record.voter,
+            support,
+            weight,
+        });
+
+        Ok(())
+    }
+
+    pub fn execute_proposal(ctx: Context<ExecuteProposal>) -> Result<()> {
+        let proposal = &mut ctx.accounts.proposal;
+        require!(!proposal.executed, BondError::ProposalExecuted);
+        let now = Clock::get()?.unix_timestamp;
+        require!(now >= proposal.end_time, BondError::VotingOpen);
+        proposal.executed = true;
+        ctx.accounts.market.proposal_active = false;
+
+        // Passing actions go through the same paths as the creator's admin instructions
+        let passed = proposal.passed();
+        if passed {
+            require!(!proposal.expired(now), BondError::ProposalExpired);
+            let bond = &mut ctx.accounts.bond;
+            match proposal.action {
+                ProposalAction::ChangeOracle { oracle } => apply_bond_oracle(bond, oracle),
+                ProposalAction::SetActive { active } => apply_bond_active(bond, active),
+                ProposalAction::SetPoolFee { fee_bps } => {
+                    let pool = ctx.accounts.pool.as_mut().ok_or(BondError::MissingPool)?;
+                    apply_pool_fee(bond, pool, fee_bps)?;
+                }
+                ProposalAction::SetTradingMode { trading_mode } => {
+                    apply_trading_mode(bond, &ctx.accounts.market, trading_mode)?;
+                }
+            }
+        }
+
+        emit!(ProposalExecutedEvent {
+            bond_id: ctx.accounts.bond.id,
+            epoch: proposal.epoch,
+            passed,
+            yes_votes: proposal.yes_votes,
+            no_votes: proposal.no_votes,
+        });
+
+        Ok(())
+    }
+
+    // Frees the proposal slot and returns the rent to the proposer once a proposal has been
+    // executed, failed its vote, or passed but was never executed in time
+    pub fn close_proposal(ctx: Context<CloseProposal>) -> Result<()> {
+        let proposal = &ctx.accounts.proposal;
+        let now = Clock::get()?.unix_timestamp;
+        let passed = proposal.passed();
+        if !proposal.executed {
+            require!(now >= proposal.end_time, BondError::VotingOpen);
+            require!(!passed || proposal.expired(now), BondError::ProposalPending);
+            ctx.accounts.market.proposal_active = false;
+        }
+
+        emit!(ProposalClosedEvent {
+            bond_id: ctx.accounts.bond.id,
+            epoch: proposal.epoch,
+            passed,
+            executed: proposal.executed,
+        });
+
+        Ok(())
+    }
+
+    // Buys unsold units from the vault at the current price and retires them from total supply
+    pub fn buyback_and_burn(ctx: Context<BuybackAndBurn>, amount: u64) -> Result<()> {
+        require!(amount > 0, BondError::InvalidAmount);
+        let bond = &mut ctx.accounts.bond;
+        let market = &mut ctx.accounts.market;
+        check_buyback_allowed(bond, market)?;
+        require!(!market.graduated, BondError::BondGraduated);
+        require!(amount <= bond.current_supply, BondError::InsufficientSupply);
+        market.bind_quote_mint(ctx.accounts.protocol_vault.vault.mint)?;
+
+        // The creator pays the current price into the vault like any other buyer
+        let payment_amount = amount.checked_mul(bond.price).unwrap();
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.creator_token_account.to_account_info(),
+            to: ctx.accounts.protocol_vault.vault.to_account_info(),
+            authority: ctx.accounts.creator.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, payment_amount)?;
+
+        // ...but the units are retired instead of credited, shrinking total supply
+        bond.current_supply = bond.current_supply.checked_sub(amount).unwrap();
+        bond.total_supply = bond.total_supply.checked_sub(amount).unwrap();
+
+        market.total_volume = market.total_volume.checked_add(payment_amount).unwrap();
+        market.reserve = market.reserve.checked_add(payment_amount).unwrap();
+        market.refresh_market_cap(bond);
+
+        emit!(BondBuybackEvent {
+            bond_id: bond.id,
+            amount,
+            price: bond.price,
+            total_supply: bond.total_supply,
+        });
+
+        Ok(())
+    }
+
+    // Buys units back from holders by lifting the best sell order, as long as it is at or
+    // below the bond price, and retires them from total supply
+    pub fn buyback_from_order_book(ctx: Context<BuybackFromOrderBook>, amount: u64) -> Result<()> {
+        require!(amount > 0, BondError::InvalidAmount);
+        let bond = &mut ctx.accounts.bond;
+        let market = &mut ctx.accounts.market;
+        check_buyback_allowed(bond, market)?;
+
+        let (ask, fill_amount, ask_dust) = {
+            let mut order_book = ctx.accounts.order_book.load_mut()?;
+            market.bind_quote_mint(order_book.quote_mint)?;
+            require_keys_eq!(
+                ctx.accounts.creator_token_account.mint,
+                order_book.quote_mint,
+                BondError::InvalidOrderAccount
+            );
+
+            let ask_index = order_book.best_ask().ok_or(BondError::NoBuybackOrders)?;
+            let ask = order_book.orders[ask_index];
+            require!(ask.price <= bond.price, BondError::NoBuybackOrders);
+
+            let fill_amount = amount.min(ask.amount);
+            let ask_dust = order_book.fill(ask_index, fill_amount);
+            (ask, fill_amount, ask_dust)
+        };
+
+        let seller_holder_bonds = &mut ctx.accounts.seller_holder_bonds;
+        require_keys_eq!(seller_holder_bonds.holder, ask.owner, BondError::InvalidOrderAccount);
+        require_keys_eq!(
+            ctx.accounts.seller_quote_account.owner,
+            ask.owner,
+            BondError::InvalidOrderAccount
+        );
+
+        // Release the seller's escrowed units, including any dust remainder, and retire
+        // the filled units instead of crediting them to anyone
+        seller_holder_bonds.locked = seller_holder_bonds
+            .locked
+            .checked_sub(fill_amount.checked_add(ask_dust).unwrap())
+            .unwrap();
+        debit_holder(
+            market,
+            seller_holder_bonds,
+            &ctx.accounts.seller_holder_page.page,
+            fill_amount,
+        )?;
+        bond.total_supply = bond.total_supply.checked_sub(fill_amount).unwrap();
+
+        // The creator pays the seller's asking price out of their own funds
+        let payment_amount = ask.price.checked_mul(fill_amount).unwrap();
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.creator_token_account.to_account_info(),
+            to: ctx.accounts.seller_quote_account.to_account_info(),
+            authority: ctx.accounts.creator.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, payment_amount)?;
+
+        market.total_volume = market.total_volume.checked_add(payment_amount).unwrap();
+        market.refresh_market_cap(bond);
+
+        emit!(OrderBookBuybackEvent {
+            bond_id: bond.id,
+            seller: ask.owner,
+            ask_order_id: ask.order_id,
+            amount: fill_amount,
+            price: ask.price,
+            total_supply: bond.total_supply,
+        });
+
+        Ok(())
+    }
+
+    pub fn configure_circuit_breaker(
+        ctx: Context<ConfigureCircuitBreaker>,
+        window_slots: u64,
+        threshold_bps: u64,
+        cooldown_slots: u64,
+    ) -> Result<()> {
+        require!(
+            threshold_bps == 0 || window_slots > 0,
+            BondError::InvalidCircuitBreaker
+        );
+
+        // A halt can only be lifted by the cooldown or resume_trading, not reconfigured away
+        let slot = Clock::get()?.slot;
+        let circuit_breaker = &mut ctx.accounts.market.circuit_breaker;
+        require!(!circuit_breaker.is_halted(slot), BondError::TradingHalted);
+        circuit_breaker.window_slots = window_slots;
+        circuit_breaker.threshold_bps = threshold_bps;
+        circuit_breaker.cooldown_slots = cooldown_slots;
+        circuit_breaker.reset_window();
+
+        Ok(())
+    }
+
+    pub fn resume_trading(ctx: Context<ResumeTrading>) -> Result<()> {
+        let circuit_breaker = &mut ctx.accounts.market.circuit_breaker;
+        require!(circuit_breaker.halted, BondError::TradingNotHalted);
+
+        // Manual review can lift a halt before (or without) a cooldown
+        circuit_breaker.halted = false;
+        circuit_breaker.reset_window();
+
+        emit!(TradingResumedEvent {
+            bond_id: ctx.accounts.bond.id,
+        });
+
+        Ok(())
+    }
+
+    pub fn open_insurance_incident(
+        ctx: Context<OpenInsuranceIncident>,
+        incident_id: u64,
+    ) -> Result<()> {
+        let incident = &mut ctx.accounts.incident;
+        incident.bond = ctx.accounts.bond.key();
+        incident.incident_id = incident_id;
+        incident.opened_at = Clock::get()?.unix_timestamp;
+        incident.total_compensation = 0;
+        incident.total_claimed = 0;
+        incident.bump = ctx.bumps.incident;
+
+        emit!(InsuranceIncidentOpenedEvent {
+            bond_id: ctx.accounts.bond.id,
+            incident_id,
+        });
+
+        Ok(())
+    }
+
+    pub fn compensate_holder(ctx: Context<CompensateHolder>, amount: u64) -> Result<()> {
+        require!(amount > 0, BondError::InvalidAmount);
+
+        // Compensation is reserved out of the mint's fund up front so claims can't overdraw it
+        let fee_ledger = &mut ctx.accounts.fee_ledger;
+        require!(
+            amount <= fee_ledger.insurance_fund,
+            BondError::InsufficientInsuranceFund
+        );
+        fee_ledger.insurance_fund -= amount;
+
+        let incident = &mut ctx.accounts.incident;
+        incident.total_compensation = incident.total_compensation.checked_add(amount).unwrap();
+
+        let insurance_claim = &mut ctx.accounts.insurance_claim;
+        insurance_claim.incident = incident.key();
+        insurance_claim.holder = ctx.accounts.holder_bonds.holder;
+        insurance_claim.mint = fee_ledger.mint;
+        insurance_claim.amount = amount;
+        insurance_claim.claimed = false;
+        insurance_claim.bump = ctx.bumps.insurance_claim;
+
+        emit!(HolderCompensatedEvent {
+            bond_id: ctx.accounts.bond.id,
+            incident_id: incident.incident_id,
+            holder: insurance_claim.holder,
+            mint: insurance_claim.mint,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn claim_insurance(ctx: Context<ClaimInsurance>) -> Result<()> {
+        let insurance_claim = &mut ctx.accounts.insurance_claim;
+        require!(!insurance_claim.claimed, BondError::NothingToClaim);
+        insurance_claim.claimed = true;
+
+        let incident = &mut ctx.accounts.incident;
+        incident.total_claimed = incident.total_claimed.checked_add(insurance_claim.amount).unwrap();
+
+        ctx.accounts.protocol_vault.transfer_out(
+            &ctx.bumps.protocol_vault,
+            ctx.accounts.holder_token_account.to_account_info(),
+            &ctx.accounts.token_program,
+            insurance_claim.amount,
+        )?;
+
+        emit!(InsuranceClaimedEvent {
+            incident_id: incident.incident_id,
+            holder: insurance_claim.holder,
+            amount: insurance_claim.amount,
+        });
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + size_of::<BondsState>(),
+        seeds = [b"bonds_state"],
+        bump
+    )]
+    pub bonds_state: Account<'info, BondsState>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateBondsState<'info> {
+    /// CHECK: Legacy bonds state; its layout and authority are checked by the handler
+    #[account(
+        mut,
+        seeds = [b"bonds_state"],
+        bump
+    )]
+    pub bonds_state: UncheckedAccount<'info>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawProtocolFees<'info> {
+    #[account(
+        seeds = [b"bonds_state"],
+        bump = bonds_state.bump,
+        has_one = authority @ BondError::Unauthorized
+    )]
+    pub bonds_state: Account<'info, BondsState>,
+    
+    #[account(
+        mut,
+        seeds = [b"fee_ledger", fee_ledger.mint.as_ref()],
+        bump = fee_ledger.bump,
+        constraint = fee_ledger.mint == protocol_vault.vault.mint @ BondError::InvalidVault
+    )]
+    pub fee_ledger: Account<'info, FeeLedger>,
+    
+    pub protocol_vault: ProtocolVault<'info>,
+    
+    #[account(mut, constraint = destination.mint == fee_ledger.mint @ BondError::InvalidVault)]
+    pub destination: Account<'info, TokenAccount>,
+    
+    pub authority: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateBondsState<'info> {
+    #[account(
+        mut,
+        seeds = [b"bonds_state"],
+        bump = bonds_state.bump,
+        has_one = authority @ BondError::Unauthorized
+    )]
+    pub bonds_state: Account<'info, BondsState>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(
+    name: String,
+    description: String,
+    category: String,
+    metric: String,
+    total_supply: u64,
+    initial_price: u64,
+    metadata_uri: String
+)]
+pub struct CreateBond<'info> {
+    #[account(
+        mut,
+        seeds = [b"bonds_state"],
+        bump = bonds_state.bump
+    )]
+    pub bonds_state: Account<'info, BondsState>,
+    
+    #[account(
+        init,
+        payer = creator,
+        space = Bond::space(&name, &description, &category, &metric, &metadata_uri),
+        seeds = [b"bond", bonds_state.bond_counter.checked_add(1).unwrap().to_le_bytes().as_ref()],
+        bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + size_of::<Market>(),
+        seeds = [b"market", bond.key().as_ref()],
+        bump
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        init_if_needed,
+        payer = creator,
+        space = 8 + size_of::<CreatorBonds>(),
+        seeds = [b"creator_bonds", creator.key().as_ref()],
+        bump
+    )]
+    pub creator_bonds: Account<'info, CreatorBonds>,
+    
+    #[account(
+        init_if_needed,
+        payer = creator,
+        space = 8 + size_of::<CreatorBondsPage>() +
+                4 + (8 * CREATOR_BONDS_PAGE_SIZE as usize), // bond_ids: Vec<u64> (max CREATOR_BONDS_PAGE_SIZE bonds)
+        seeds = [
+            b"creator_bonds_page",
+            creator.key().as_ref(),
+            (creator_bonds.bond_count / CREATOR_BONDS_PAGE_SIZE).to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub creator_bonds_page: Account<'info, CreatorBondsPage>,
+    
+    /// CHECK: Oracle account that will provide metric updates
+    pub oracle: AccountInfo<'info>,
+    
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateCreatorBonds<'info> {
+    /// CHECK: Legacy creator index; its layout is checked by the handler
+    #[account(
+        mut,
+        seeds = [b"creator_bonds", creator.key().as_ref()],
+        bump
+    )]
+    pub creator_bonds: UncheckedAccount<'info>,
+    
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + size_of::<CreatorBondsPage>() +
+                4 + (8 * CREATOR_BONDS_PAGE_SIZE as usize), // bond_ids: Vec<u64> (max CREATOR_BONDS_PAGE_SIZE bonds)
+        seeds = [b"creator_bonds_page", creator.key().as_ref(), 0u64.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub creator_bonds_page: Account<'info, CreatorBondsPage>,
+    
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMetric<'info> {
+    #[account(
+        mut,
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        seeds = [b"batch_auction", bond.key().as_ref(), market.batch_epoch.to_le_bytes().as_ref()],
+        bump = batch_auction.bump
+    )]
+    pub batch_auction: Option<Account<'info, BatchAuction>>,
+    
+    #[account(
+        mut,
+        seeds = [b"composite", bond.key().as_ref()],
+        bump = composite_metric.bump
+    )]
+    pub composite_metric: Option<Account<'info, CompositeMetric>>,
+    
+    #[account(
+        mut,
+        seeds = [b"metric_history", bond.key().as_ref()],
+        bump
+    )]
+    pub metric_history: Option<AccountLoader<'info, MetricHistory>>,
+    
+    /// CHECK: Verified in instruction
+    pub oracle: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct TradeBond<'info> {
+    #[account(
+        mut,
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + size_of::<HolderBonds>(),
+        seeds = [b"holder_bonds", bond.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub holder_bonds: Account<'info, HolderBonds>,
+    
+    pub holder_page: HolderPageAccount<'info>,
+    
+    #[account(
+        seeds = [b"bonds_state"],
+        bump = bonds_state.bump
+    )]
+    pub bonds_state: Account<'info, BondsState>,
+    
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + size_of::<FeeLedger>(),
+        seeds = [b"fee_ledger", protocol_vault.vault.mint.as_ref()],
+        bump
+    )]
+    pub fee_ledger: Account<'info, FeeLedger>,
+    
+    pub protocol_vault: ProtocolVault<'info>,
+    
+    #[account(mut)]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub seller_token_account: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"referrer", referrer_stats.referrer.as_ref()],
+        bump = referrer_stats.bump,
+        constraint = referrer_stats.referrer != buyer.key() @ BondError::SelfReferral
+    )]
+    pub referrer_stats: Option<Account<'info, ReferrerStats>>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct TransferBond<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        seeds = [b"holder_bonds", bond.key().as_ref(), owner.key().as_ref()],
+        bump = from_holder_bonds.bump
+    )]
+    pub from_holder_bonds: Account<'info, HolderBonds>,
+    
+    /// CHECK: Recipient wallet, only used to derive their position
+    #[account(constraint = recipient.key() != owner.key() @ BondError::SelfTransfer)]
+    pub recipient: UncheckedAccount<'info>,
+    
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + size_of::<HolderBonds>(),
+        seeds = [b"holder_bonds", bond.key().as_ref(), recipient.key().as_ref()],
+        bump
+    )]
+    pub to_holder_bonds: Account<'info, HolderBonds>,
+    
+    pub from_holder_page: HolderPageAccount<'info>,
+    
+    pub to_holder_page: HolderPageAccount<'info>,
+    
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(name: String, description: String, metric: String, metadata_uri: String)]
+pub struct UpdateBondMetadata<'info> {
+    #[account(
+        mut,
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump,
+        has_one = creator @ BondError::Unauthorized,
+        realloc = Bond::space(&name, &description, &bond.category, &metric, &metadata_uri),
+        realloc::payer = creator,
+        realloc::zero = false
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreatePool<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump,
+        has_one = creator @ BondError::Unauthorized
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + size_of::<Pool>(),
+        seeds = [b"pool", bond.key().as_ref()],
+        bump
+    )]
+    pub pool: Account<'info, Pool>,
+    
+    pub quote_mint: Account<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = creator,
+        token::mint = quote_mint,
+        token::authority = pool,
+        seeds = [b"pool_vault", pool.key().as_ref()],
+        bump
+    )]
+    pub quote_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        init,
+        payer = creator,
+        mint::decimals = 9,
+        mint::authority = pool,
+        seeds = [b"lp_mint", pool.key().as_ref()],
+        bump
+    )]
+    pub lp_mint: Account<'info, Mint>,
+    
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct PoolLiquidity<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", bond.key().as_ref()],
+        bump = pool.bump,
+        has_one = bond @ BondError::PoolBondMismatch,
+        has_one = quote_vault @ BondError::InvalidPoolAccount,
+        has_one = lp_mint @ BondError::InvalidPoolAccount
+    )]
+    pub pool: Account<'info, Pool>,
+    
+    #[account(mut)]
+    pub quote_vault: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub lp_mint: Account<'info, Mint>,
+    
+    #[account(
+        init_if_needed,
+        payer = provider,
+        space = 8 + size_of::<HolderBonds>(),
+        seeds = [b"holder_bonds", bond.key().as_ref(), provider.key().as_ref()],
+        bump
+    )]
+    pub holder_bonds: Account<'info, HolderBonds>,
+    
+    pub holder_page: HolderPageAccount<'info>,
+    
+    #[account(
+        mut,
+        constraint = provider_quote_account.mint == pool.quote_mint,
+        constraint = provider_quote_account.owner == provider.key()
+    )]
+    pub provider_quote_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = provider_lp_account.mint == lp_mint.key(),
+        constraint = provider_lp_account.owner == provider.key()
+    )]
+    pub provider_lp_account: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub provider: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SwapBonds<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", bond.key().as_ref()],
+        bump = pool.bump,
+        has_one = bond @ BondError::PoolBondMismatch,
+        has_one = quote_vault @ BondError::InvalidPoolAccount
+    )]
+    pub pool: Account<'info, Pool>,
+    
+    #[account(mut)]
+    pub quote_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        init_if_needed,
+        payer = trader,
+        space = 8 + size_of::<HolderBonds>(),
+        seeds = [b"holder_bonds", bond.key().as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub holder_bonds: Account<'info, HolderBonds>,
+    
+    pub holder_page: HolderPageAccount<'info>,
+    
+    #[account(
+        mut,
+        constraint = trader_quote_account.mint == pool.quote_mint,
+        constraint = trader_quote_account.owner == trader.key()
+    )]
+    pub trader_quote_account: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub trader: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GraduateBond<'info> {
+    #[account(
+        seeds = [b"bonds_state"],
+        bump = bonds_state.bump
+    )]
+    pub bonds_state: Account<'info, BondsState>,
+    
+    #[account(
+        mut,
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + size_of::<Pool>(),
+        seeds = [b"pool", bond.key().as_ref()],
+        bump
+    )]
+    pub pool: Account<'info, Pool>,
+    
+    #[account(constraint = quote_mint.key() == protocol_vault.vault.mint @ BondError::InvalidVault)]
+    pub quote_mint: Account<'info, Mint>,
+    
+    #[account(
+        init_if_needed,
+        payer = payer,
+        token::mint = quote_mint,
+        token::authority = pool,
+        seeds = [b"pool_vault", pool.key().as_ref()],
+        bump
+    )]
+    pub quote_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        init_if_needed,
+        payer = payer,
+        mint::decimals = 9,
+        mint::authority = pool,
+        seeds = [b"lp_mint", pool.key().as_ref()],
+        bump
+    )]
+    pub lp_mint: Account<'info, Mint>,
+    
+    // Holds the LP share of migrated liquidity; owned by the pool, which never moves it
+    #[account(
+        init_if_needed,
+        payer = payer,
+        token::mint = lp_mint,
+        token::authority = pool,
+        seeds = [b"lp_lock", pool.key().as_ref()],
+        bump
+    )]
+    pub lp_lock: Account<'info, TokenAccount>,
+    
+    pub protocol_vault: ProtocolVault<'info>,
+    
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CreateOrderBook<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump,
+        has_one = creator @ BondError::Unauthorized
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + size_of::<OrderBook>(),
+        seeds = [b"order_book", bond.key().as_ref()],
+        bump
+    )]
+    pub order_book: AccountLoader<'info, OrderBook>,
+    
+    pub quote_mint: Account<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = creator,
+        token::mint = quote_mint,
+        token::authority = order_book,
+        seeds = [b"book_vault", order_book.key().as_ref()],
+        bump
+    )]
+    pub quote_vault: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceOrder<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"order_book", bond.key().as_ref()],
+        bump
+    )]
+    pub order_book: AccountLoader<'info, OrderBook>,
+    
+    #[account(
+        mut,
+        seeds = [b"book_vault", order_book.key().as_ref()],
+        bump
+    )]
+    pub quote_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + size_of::<HolderBonds>(),
+        seeds = [b"holder_bonds", bond.key().as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    pub holder_bonds: Account<'info, HolderBonds>,
+    
+    #[account(
+        mut,
+        constraint = owner_quote_account.mint == quote_vault.mint,
+        constraint = owner_quote_account.owner == owner.key()
+    )]
+    pub owner_quote_account: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOrder<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"order_book", bond.key().as_ref()],
+        bump
+    )]
+    pub order_book: AccountLoader<'info, OrderBook>,
+    
+    #[account(
+        mut,
+        seeds = [b"book_vault", order_book.key().as_ref()],
+        bump
+    )]
+    pub quote_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"holder_bonds", bond.key().as_ref(), owner.key().as_ref()],
+        bump = holder_bonds.bump
+    )]
+    pub holder_bonds: Account<'info, HolderBonds>,
+    
+    #[account(
+        mut,
+        constraint = owner_quote_account.mint == quote_vault.mint,
+        constraint = owner_quote_account.owner == owner.key()
+    )]
+    pub owner_quote_account: Account<'info, TokenAccount>,
+    
+    pub owner: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct MatchOrders<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        seeds = [b"order_book", bond.key().as_ref()],
+        bump
+    )]
+    pub order_book: AccountLoader<'info, OrderBook>,
+    
+    #[account(
+        mut,
+        seeds = [b"book_vault", order_book.key().as_ref()],
+        bump
+    )]
+    pub quote_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"holder_bonds", bond.key().as_ref(), buyer_holder_bonds.holder.as_ref()],
+        bump = buyer_holder_bonds.bump
+    )]
+    pub buyer_holder_bonds: Account<'info, HolderBonds>,
+    
+    #[account(
+        mut,
+        seeds = [b"holder_bonds", bond.key().as_ref(), seller_holder_bonds.holder.as_ref()],
+        bump = seller_holder_bonds.bump
+    )]
+    pub seller_holder_bonds: Account<'info, HolderBonds>,
+    
+    pub buyer_holder_page: HolderPageAccount<'info>,
+    
+    pub seller_holder_page: HolderPageAccount<'info>,
+    
+    #[account(mut, constraint = buyer_quote_account.mint == quote_vault.mint)]
+    pub buyer_quote_account: Account<'info, TokenAccount>,
+    
+    #[account(mut, constraint = seller_quote_account.mint == quote_vault.mint)]
+    pub seller_quote_account: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetTradingMode<'info> {
+    #[account(
+        mut,
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump,
+        has_one = creator @ BondError::Unauthorized
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitBatchOrder<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + size_of::<BatchAuction>() +
+                4 + (size_of::<BatchOrder>() * MAX_BATCH_ORDERS), // orders: Vec<BatchOrder> (max MAX_BATCH_ORDERS orders)
+        seeds = [b"batch_auction", bond.key().as_ref(), market.batch_epoch.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub batch_auction: Account<'info, BatchAuction>,
+    
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + size_of::<HolderBonds>(),
+        seeds = [b"holder_bonds", bond.key().as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    pub holder_bonds: Account<'info, HolderBonds>,
+    
+    #[account(
+        seeds = [b"bonds_state"],
+        bump = bonds_state.bump
+    )]
+    pub bonds_state: Account<'info, BondsState>,
+    
+    pub protocol_vault: ProtocolVault<'info>,
+    
+    #[account(
+        mut,
+        constraint = owner_token_account.mint == protocol_vault.vault.mint,
+        constraint = owner_token_account.owner == owner.key()
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SettleBatchOrder<'info> {
+    #[account(
+        mut,
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        seeds = [b"batch_auction", bond.key().as_ref(), batch_auction.epoch.to_le_bytes().as_ref()],
+        bump = batch_auction.bump
+    )]
+    pub batch_auction: Account<'info, BatchAuction>,
+    
+    #[account(
+        mut,
+        seeds = [b"holder_bonds", bond.key().as_ref(), holder_bonds.holder.as_ref()],
+        bump = holder_bonds.bump
+    )]
+    pub holder_bonds: Account<'info, HolderBonds>,
+    
+    pub holder_page: HolderPageAccount<'info>,
+    
+    #[account(
+        seeds = [b"bonds_state"],
+        bump = bonds_state.bump
+    )]
+    pub bonds_state: Account<'info, BondsState>,
+    
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + size_of::<FeeLedger>(),
+        seeds = [b"fee_ledger", protocol_vault.vault.mint.as_ref()],
+        bump
+    )]
+    pub fee_ledger: Account<'info, FeeLedger>,
+    
+    pub protocol_vault: ProtocolVault<'info>,
+    
+    #[account(mut, constraint = owner_token_account.mint == protocol_vault.vault.mint)]
+    pub owner_token_account: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CommitTrade<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        init,
+        payer = trader,
+        space = 8 + size_of::<TradeCommitment>(),
+        seeds = [b"trade_commitment", bond.key().as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub trade_commitment: Account<'info, TradeCommitment>,
+    
+    #[account(mut)]
+    pub trader: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevealTrade<'info> {
+    pub trade: TradeBond<'info>,
+    
+    #[account(
+        mut,
+        close = trader,
+        seeds = [b"trade_commitment", trade.bond.key().as_ref(), trader.key().as_ref()],
+        bump = trade_commitment.bump
+    )]
+    pub trade_commitment: Account<'info, TradeCommitment>,
+    
+    #[account(mut, address = trade.buyer.key())]
+    pub trader: SystemAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ForfeitCommitment<'info> {
+    #[account(
+        seeds = [b"bonds_state"],
+        bump = bonds_state.bump
+    )]
+    pub bonds_state: Account<'info, BondsState>,
+    
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        close = trader,
+        seeds = [b"trade_commitment", bond.key().as_ref(), trader.key().as_ref()],
+        bump = trade_commitment.bump
+    )]
+    pub trade_commitment: Account<'info, TradeCommitment>,
+    
+    #[account(mut)]
+    pub trader: SystemAccount<'info>,
+    
+    #[account(mut, address = bonds_state.authority)]
+    pub authority: SystemAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetTradeLimits<'info> {
+    #[account(
+        mut,
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump,
+        has_one = creator @ BondError::Unauthorized
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigurePresale<'info> {
+    #[account(
+        mut,
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump,
+        has_one = creator @ BondError::Unauthorized
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct PresaleBuy<'info> {
+    pub trade: TradeBond<'info>,
+    
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + size_of::<PresaleAllocation>(),
+        seeds = [b"presale_allocation", trade.bond.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub presale_allocation: Account<'info, PresaleAllocation>,
+    
+    #[account(mut, address = trade.buyer.key())]
+    pub buyer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct StartDutchAuction<'info> {
+    #[account(
+        mut,
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump,
+        has_one = creator @ BondError::Unauthorized
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + size_of::<DutchAuction>(),
+        seeds = [b"dutch_auction", bond.key().as_ref()],
+        bump
+    )]
+    pub dutch_auction: Account<'info, DutchAuction>,
+    
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BidDutchAuction<'info> {
+    #[account(
+        mut,
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        seeds = [b"dutch_auction", bond.key().as_ref()],
+        bump = dutch_auction.bump
+    )]
+    pub dutch_auction: Account<'info, DutchAuction>,
+    
+    #[account(
+        init_if_needed,
+        payer = bidder,
+        space = 8 + size_of::<DutchBid>(),
+        seeds = [b"dutch_bid", dutch_auction.key().as_ref(), bidder.key().as_ref()],
+        bump
+    )]
+    pub dutch_bid: Account<'info, DutchBid>,
+    
+    #[account(
+        init_if_needed,
+        payer = bidder,
+        space = 8 + size_of::<HolderBonds>(),
+        seeds = [b"holder_bonds", bond.key().as_ref(), bidder.key().as_ref()],
+        bump
+    )]
+    pub holder_bonds: Account<'info, HolderBonds>,
+    
+    #[account(
+        seeds = [b"bonds_state"],
+        bump = bonds_state.bump
+    )]
+    pub bonds_state: Account<'info, BondsState>,
+    
+    pub protocol_vault: ProtocolVault<'info>,
+    
+    #[account(
+        mut,
+        constraint = bidder_token_account.mint == protocol_vault.vault.mint,
+        constraint = bidder_token_account.owner == bidder.key()
+    )]
+    pub bidder_token_account: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeDutchAuction<'info> {
+    #[account(
+        mut,
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        seeds = [b"dutch_auction", bond.key().as_ref()],
+        bump = dutch_auction.bump
+    )]
+    pub dutch_auction: Account<'info, DutchAuction>,
+}
+
+#[derive(Accounts)]
+pub struct SettleDutchBid<'info> {
+    #[account(
+        mut,
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        seeds = [b"dutch_auction", bond.key().as_ref()],
+        bump = dutch_auction.bump
+    )]
+    pub dutch_auction: Account<'info, DutchAuction>,
+    
+    #[account(
+        mut,
+        seeds = [b"dutch_bid", dutch_auction.key().as_ref(), dutch_bid.bidder.as_ref()],
+        bump = dutch_bid.bump
+    )]
+    pub dutch_bid: Account<'info, DutchBid>,
+    
+    #[account(
+        mut,
+        seeds = [b"holder_bonds", bond.key().as_ref(), dutch_bid.bidder.as_ref()],
+        bump = holder_bonds.bump
+    )]
+    pub holder_bonds: Account<'info, HolderBonds>,
+    
+    pub holder_page: HolderPageAccount<'info>,
+    
+    #[account(
+        seeds = [b"bonds_state"],
+        bump = bonds_state.bump
+    )]
+    pub bonds_state: Account<'info, BondsState>,
+    
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + size_of::<FeeLedger>(),
+        seeds = [b"fee_ledger", protocol_vault.vault.mint.as_ref()],
+        bump
+    )]
+    pub fee_ledger: Account<'info, FeeLedger>,
+    
+    pub protocol_vault: ProtocolVault<'info>,
+    
+    #[account(mut, constraint = bidder_token_account.mint == protocol_vault.vault.mint)]
+    pub bidder_token_account: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterReferrer<'info> {
+    #[account(
+        init,
+        payer = referrer,
+        space = 8 + size_of::<ReferrerStats>(),
+        seeds = [b"referrer", referrer.key().as_ref()],
+        bump
+    )]
+    pub referrer_stats: Account<'info, ReferrerStats>,
+    
+    #[account(mut)]
+    pub referrer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimReferralRewards<'info> {
+    #[account(
+        mut,
+        seeds = [b"referrer", referrer.key().as_ref()],
+        bump = referrer_stats.bump,
+        has_one = referrer @ BondError::Unauthorized
+    )]
+    pub referrer_stats: Account<'info, ReferrerStats>,
+    
+    pub protocol_vault: ProtocolVault<'info>,
+    
+    #[account(mut, constraint = referrer_token_account.mint == protocol_vault.vault.mint)]
+    pub referrer_token_account: Account<'info, TokenAccount>,
+    
+    pub referrer: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CreateLendingPool<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump,
+        has_one = creator @ BondError::Unauthorized
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + size_of::<LendingPool>(),
+        seeds = [b"lending_pool", bond.key().as_ref()],
+        bump
+    )]
+    pub lending_pool: Account<'info, LendingPool>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    pub quote_mint: Account<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = creator,
+        token::mint = quote_mint,
+        token::authority = lending_pool,
+        seeds = [b"lending_vault", lending_pool.key().as_ref()],
+        bump
+    )]
+    pub lending_vault: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct LenderLiquidity<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"lending_pool", bond.key().as_ref()],
+        bump = lending_pool.bump
+    )]
+    pub lending_pool: Account<'info, LendingPool>,
+    
+    #[account(mut, address = lending_pool.quote_vault @ BondError::InvalidVault)]
+    pub lending_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        init_if_needed,
+        payer = lender,
+        space = 8 + size_of::<LenderDeposit>(),
+        seeds = [b"lender_deposit", lending_pool.key().as_ref(), lender.key().as_ref()],
+        bump
+    )]
+    pub lender_deposit: Account<'info, LenderDeposit>,
+    
+    #[account(
+        mut,
+        constraint = lender_token_account.mint == lending_pool.quote_mint,
+        constraint = lender_token_account.owner == lender.key()
+    )]
+    pub lender_token_account: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub lender: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Borrow<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        seeds = [b"lending_pool", bond.key().as_ref()],
+        bump = lending_pool.bump
+    )]
+    pub lending_pool: Account<'info, LendingPool>,
+    
+    #[account(mut, address = lending_pool.quote_vault @ BondError::InvalidVault)]
+    pub lending_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"holder_bonds", bond.key().as_ref(), borrower.key().as_ref()],
+        bump = holder_bonds.bump
+    )]
+    pub holder_bonds: Account<'info, HolderBonds>,
+    
+    #[account(
+        init_if_needed,
+        payer = borrower,
+        space = 8 + size_of::<Loan>(),
+        seeds = [b"loan", bond.key().as_ref(), borrower.key().as_ref()],
+        bump
+    )]
+    pub loan: Account<'info, Loan>,
+    
+    #[account(mut, constraint = borrower_token_account.mint == lending_pool.quote_mint)]
+    pub borrower_token_account: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ManageLoan<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"lending_pool", bond.key().as_ref()],
+        bump = lending_pool.bump
+    )]
+    pub lending_pool: Account<'info, LendingPool>,
+    
+    #[account(mut, address = lending_pool.quote_vault @ BondError::InvalidVault)]
+    pub lending_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"holder_bonds", bond.key().as_ref(), borrower.key().as_ref()],
+        bump = holder_bonds.bump
+    )]
+    pub holder_bonds: Account<'info, HolderBonds>,
+    
+    #[account(
+        mut,
+        seeds = [b"loan", bond.key().as_ref(), borrower.key().as_ref()],
+        bump = loan.bump
+    )]
+    pub loan: Account<'info, Loan>,
+    
+    #[account(mut, constraint = borrower_token_account.mint == lending_pool.quote_mint)]
+    pub borrower_token_account: Account<'info, TokenAccount>,
+    
+    pub borrower: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct LiquidateLoan<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        seeds = [b"lending_pool", bond.key().as_ref()],
+        bump = lending_pool.bump
+    )]
+    pub lending_pool: Account<'info, LendingPool>,
+    
+    #[account(mut, address = lending_pool.quote_vault @ BondError::InvalidVault)]
+    pub lending_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"loan", bond.key().as_ref(), loan.borrower.as_ref()],
+        bump = loan.bump,
+        constraint = loan.borrower != liquidator.key() @ BondError::SelfLiquidation
+    )]
+    pub loan: Account<'info, Loan>,
+    
+    #[account(
+        mut,
+        seeds = [b"holder_bonds", bond.key().as_ref(), loan.borrower.as_ref()],
+        bump = borrower_holder_bonds.bump
+    )]
+    pub borrower_holder_bonds: Account<'info, HolderBonds>,
+    
+    pub borrower_holder_page: HolderPageAccount<'info>,
+    
+    #[account(
+        init_if_needed,
+        payer = liquidator,
+        space = 8 + size_of::<HolderBonds>(),
+        seeds = [b"holder_bonds", bond.key().as_ref(), liquidator.key().as_ref()],
+        bump
+    )]
+    pub liquidator_holder_bonds: Account<'info, HolderBonds>,
+    
+    pub liquidator_holder_page: HolderPageAccount<'info>,
+    
+    #[account(mut, constraint = liquidator_token_account.mint == lending_pool.quote_mint)]
+    pub liquidator_token_account: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub liquidator: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateStakingPool<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump,
+        has_one = creator @ BondError::Unauthorized
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + size_of::<StakingPool>(),
+        seeds = [b"staking_pool", bond.key().as_ref()],
+        bump
+    )]
+    pub staking_pool: Account<'info, StakingPool>,
+    
+    pub reward_mint: Account<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = creator,
+        token::mint = reward_mint,
+        token::authority = staking_pool,
+        seeds = [b"staking_vault", staking_pool.key().as_ref()],
+        bump
+    )]
+    pub reward_vault: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct FundStakingRewards<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"staking_pool", bond.key().as_ref()],
+        bump = staking_pool.bump
+    )]
+    pub staking_pool: Account<'info, StakingPool>,
+    
+    #[account(mut, address = staking_pool.reward_vault @ BondError::InvalidVault)]
+    pub reward_vault: Account<'info, TokenAccount>,
+    
+    #[account(mut, constraint = funder_token_account.mint == staking_pool.reward_mint)]
+    pub funder_token_account: Account<'info, TokenAccount>,
+    
+    pub funder: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct StakeBonds<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"staking_pool", bond.key().as_ref()],
+        bump = staking_pool.bump
+    )]
+    pub staking_pool: Account<'info, StakingPool>,
+    
+    #[account(
+        init_if_needed,
+        payer = staker,
+        space = 8 + size_of::<StakePosition>(),
+        seeds = [b"stake_position", staking_pool.key().as_ref(), staker.key().as_ref()],
+        bump
+    )]
+    pub stake_position: Account<'info, StakePosition>,
+    
+    #[account(
+        mut,
+        seeds = [b"holder_bonds", bond.key().as_ref(), staker.key().as_ref()],
+        bump = holder_bonds.bump
+    )]
+    pub holder_bonds: Account<'info, HolderBonds>,
+    
+    #[account(mut)]
+    pub staker: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimStakingRewards<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"staking_pool", bond.key().as_ref()],
+        bump = staking_pool.bump
+    )]
+    pub staking_pool: Account<'info, StakingPool>,
+    
+    #[account(
+        mut,
+        seeds = [b"stake_position", staking_pool.key().as_ref(), staker.key().as_ref()],
+        bump = stake_position.bump
+    )]
+    pub stake_position: Account<'info, StakePosition>,
+    
+    #[account(mut, address = staking_pool.reward_vault @ BondError::InvalidVault)]
+    pub reward_vault: Account<'info, TokenAccount>,
+    
+    #[account(mut, constraint = staker_token_account.mint == staking_pool.reward_mint)]
+    pub staker_token_account: Account<'info, TokenAccount>,
+    
+    pub staker: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(threshold: u64, resolve_time: i64)]
+pub struct CreatePredictionMarket<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + size_of::<PredictionMarket>(),
+        seeds = [
+            b"prediction",
+            bond.key().as_ref(),
+            threshold.to_le_bytes().as_ref(),
+            resolve_time.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub prediction_market: Account<'info, PredictionMarket>,
+    
+    pub collateral_mint: Account<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = creator,
+        token::mint = collateral_mint,
+        token::authority = prediction_market,
+        seeds = [b"prediction_vault", prediction_market.key().as_ref()],
+        bump
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        init,
+        payer = creator,
+        mint::decimals = collateral_mint.decimals,
+        mint::authority = prediction_market,
+        seeds = [b"yes_mint", prediction_market.key().as_ref()],
+        bump
+    )]
+    pub yes_mint: Account<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = creator,
+        mint::decimals = collateral_mint.decimals,
+        mint::authority = prediction_market,
+        seeds = [b"no_mint", prediction_market.key().as_ref()],
+        bump
+    )]
+    pub no_mint: Account<'info, Mint>,
+    
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct PredictionShares<'info> {
+    #[account(mut)]
+    pub prediction_market: Account<'info, PredictionMarket>,
+    
+    #[account(mut, address = prediction_market.collateral_vault @ BondError::InvalidVault)]
+    pub collateral_vault: Account<'info, TokenAccount>,
+    
+    #[account(mut, address = prediction_market.yes_mint @ BondError::InvalidPredictionMint)]
+    pub yes_mint: Account<'info, Mint>,
+    
+    #[account(mut, address = prediction_market.no_mint @ BondError::InvalidPredictionMint)]
+    pub no_mint: Account<'info, Mint>,
+    
+    #[account(mut, constraint = user_collateral_account.mint == prediction_market.collateral_mint)]
+    pub user_collateral_account: Account<'info, TokenAccount>,
+    
+    #[account(mut, constraint = user_yes_account.mint == yes_mint.key())]
+    pub user_yes_account: Account<'info, TokenAccount>,
+    
+    #[account(mut, constraint = user_no_account.mint == no_mint.key())]
+    pub user_no_account: Account<'info, TokenAccount>,
+    
+    pub user: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ResolvePredictionMarket<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(mut, has_one = bond @ BondError::MarketBondMismatch)]
+    pub prediction_market: Account<'info, PredictionMarket>,
+    
+    #[account(
+        seeds = [b"metric_history", bond.key().as_ref()],
+        bump
+    )]
+    pub metric_history: Option<AccountLoader<'info, MetricHistory>>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimPredictionPayout<'info> {
+    pub prediction_market: Account<'info, PredictionMarket>,
+    
+    #[account(mut, address = prediction_market.collateral_vault @ BondError::InvalidVault)]
+    pub collateral_vault: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub winning_mint: Account<'info, Mint>,
+    
+    #[account(mut, constraint = user_winning_account.mint == winning_mint.key())]
+    pub user_winning_account: Account<'info, TokenAccount>,
+    
+    #[account(mut, constraint = user_collateral_account.mint == prediction_market.collateral_mint)]
+    pub user_collateral_account: Account<'info, TokenAccount>,
+    
+    pub user: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CreatePerpMarket<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump,
+        has_one = creator @ BondError::Unauthorized
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + size_of::<PerpMarket>(),
+        seeds = [b"perp_market", bond.key().as_ref()],
+        bump
+    )]
+    pub perp_market: Account<'info, PerpMarket>,
+    
+    pub collateral_mint: Account<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = creator,
+        token::mint = collateral_mint,
+        token::authority = perp_market,
+        seeds = [b"perp_vault", perp_market.key().as_ref()],
+        bump
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct OpenPerpPosition<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        seeds = [b"perp_market", bond.key().as_ref()],
+        bump = perp_market.bump
+    )]
+    pub perp_market: Account<'info, PerpMarket>,
+    
+    #[account(mut, address = perp_market.collateral_vault @ BondError::InvalidVault)]
+    pub collateral_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        init_if_needed,
+        payer = trader,
+        space = 8 + size_of::<PerpPosition>(),
+        seeds = [b"perp_position", perp_market.key().as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub perp_position: Account<'info, PerpPosition>,
+    
+    #[account(mut, constraint = trader_token_account.mint == perp_market.collateral_mint)]
+    pub trader_token_account: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub trader: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PerpLiquidity<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"perp_market", bond.key().as_ref()],
+        bump = perp_market.bump
+    )]
+    pub perp_market: Account<'info, PerpMarket>,
+    
+    #[account(mut, address = perp_market.collateral_vault @ BondError::InvalidVault)]
+    pub collateral_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        init_if_needed,
+        payer = provider,
+        space = 8 + size_of::<PerpLpPosition>(),
+        seeds = [b"perp_lp", perp_market.key().as_ref(), provider.key().as_ref()],
+        bump
+    )]
+    pub perp_lp_position: Account<'info, PerpLpPosition>,
+    
+    #[account(
+        mut,
+        constraint = provider_token_account.mint == perp_market.collateral_mint,
+        constraint = provider_token_account.owner == provider.key()
+    )]
+    pub provider_token_account: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub provider: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClosePerpPosition<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        seeds = [b"perp_market", bond.key().as_ref()],
+        bump = perp_market.bump
+    )]
+    pub perp_market: Account<'info, PerpMarket>,
+    
+    #[account(mut, address = perp_market.collateral_vault @ BondError::InvalidVault)]
+    pub collateral_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"perp_position", perp_market.key().as_ref(), trader.key().as_ref()],
+        bump = perp_position.bump
+    )]
+    pub perp_position: Account<'info, PerpPosition>,
+    
+    #[account(mut, constraint = trader_token_account.mint == perp_market.collateral_mint)]
+    pub trader_token_account: Account<'info, TokenAccount>,
+    
+    pub trader: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct LiquidatePerpPosition<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"perp_market", bond.key().as_ref()],
+        bump = perp_market.bump
+    )]
+    pub perp_market: Account<'info, PerpMarket>,
+    
+    #[account(mut, address = perp_market.collateral_vault @ BondError::InvalidVault)]
+    pub collateral_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"perp_position", perp_market.key().as_ref(), perp_position.trader.as_ref()],
+        bump = perp_position.bump
+    )]
+    pub perp_position: Account<'info, PerpPosition>,
+    
+    #[account(mut, constraint = liquidator_token_account.mint == perp_market.collateral_mint)]
+    pub liquidator_token_account: Account<'info, TokenAccount>,
+    
+    pub liquidator: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(basket_id: u64)]
+pub struct CreateBasket<'info> {
+    #[account(
+        init,
+        payer = manager,
+        space = Basket::SPACE,
+        seeds = [b"basket", manager.key().as_ref(), basket_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub basket: Account<'info, Basket>,
+    
+    #[account(
+        init,
+        payer = manager,
+        mint::decimals = 9,
+        mint::authority = basket,
+        seeds = [b"basket_mint", basket.key().as_ref()],
+        bump
+    )]
+    pub share_mint: Account<'info, Mint>,
+    
+    #[account(mut)]
+    pub manager: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct RebalanceBasket<'info> {
+    #[account(
+        mut,
+        seeds = [b"basket", manager.key().as_ref(), basket.basket_id.to_le_bytes().as_ref()],
+        bump = basket.bump,
+        has_one = manager @ BondError::Unauthorized
+    )]
+    pub basket: Account<'info, Basket>,
+    
+    #[account(mut)]
+    pub manager: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BasketShares<'info> {
+    #[account(
+        seeds = [b"basket", basket.manager.as_ref(), basket.basket_id.to_le_bytes().as_ref()],
+        bump = basket.bump
+    )]
+    pub basket: Account<'info, Basket>,
+    
+    #[account(mut, address = basket.share_mint @ BondError::InvalidBasketMint)]
+    pub share_mint: Account<'info, Mint>,
+    
+    #[account(mut, constraint = user_share_account.mint == share_mint.key())]
+    pub user_share_account: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigureCompositeMetric<'info> {
+    #[account(
+        mut,
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump,
+        has_one = creator @ BondError::Unauthorized
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        init,
+        payer = creator,
+        space = CompositeMetric::SPACE,
+        seeds = [b"composite", bond.key().as_ref()],
+        bump
+    )]
+    pub composite_metric: Account<'info, CompositeMetric>,
+    
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(capacity: u32)]
+pub struct CreateMetricHistory<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump,
+        has_one = creator @ BondError::Unauthorized
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        init,
+        payer = creator,
+        space = MetricHistory::space(capacity),
+        seeds = [b"metric_history", bond.key().as_ref()],
+        bump
+    )]
+    pub metric_history: AccountLoader<'info, MetricHistory>,
+    
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ReadMetricHistory<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        seeds = [b"metric_history", bond.key().as_ref()],
+        bump
+    )]
+    pub metric_history: AccountLoader<'info, MetricHistory>,
+}
+
+#[derive(Accounts)]
+pub struct SetPoolFee<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump,
+        has_one = creator @ BondError::Unauthorized
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", bond.key().as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, Pool>,
+    
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateProposal<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        init,
+        payer = proposer,
+        space = 8 + size_of::<Proposal>(),
+        seeds = [
+            b"proposal",
+            bond.key().as_ref(),
+            (market.governance_epoch + 1).to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+    
+    #[account(
+        seeds = [b"holder_bonds", bond.key().as_ref(), proposer.key().as_ref()],
+        bump = proposer_holder_bonds.bump
+    )]
+    pub proposer_holder_bonds: Account<'info, HolderBonds>,
+    
+    #[account(
+        seeds = [b"pool", bond.key().as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Option<Account<'info, Pool>>,
+    
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CastVote<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"proposal", bond.key().as_ref(), proposal.epoch.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+    
+    #[account(
+        init,
+        payer = voter,
+        space = 8 + size_of::<VoteRecord>(),
+        seeds = [b"vote", proposal.key().as_ref(), voter.key().as_ref()],
+        bump
+    )]
+    pub vote_record: Account<'info, VoteRecord>,
+    
+    #[account(
+        seeds = [b"holder_bonds", bond.key().as_ref(), voter.key().as_ref()],
+        bump = voter_holder_bonds.bump
+    )]
+    pub voter_holder_bonds: Account<'info, HolderBonds>,
+    
+    #[account(mut)]
+    pub voter: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteProposal<'info> {
+    #[account(
+        mut,
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        seeds = [b"proposal", bond.key().as_ref(), proposal.epoch.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", bond.key().as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Option<Account<'info, Pool>>,
+}
+
+#[derive(Accounts)]
+pub struct CloseProposal<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        close = proposer,
+        seeds = [b"proposal", bond.key().as_ref(), proposal.epoch.to_le_bytes().as_ref()],
+        bump = proposal.bump,
+        has_one = proposer @ BondError::Unauthorized
+    )]
+    pub proposal: Account<'info, Proposal>,
+    
+    /// CHECK: Receives the proposal's rent, checked against proposal.proposer
+    #[account(mut)]
+    pub proposer: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct BuybackAndBurn<'info> {
+    #[account(
+        mut,
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump,
+        has_one = creator @ BondError::Unauthorized
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    pub protocol_vault: ProtocolVault<'info>,
+    
+    #[account(mut, constraint = creator_token_account.mint == protocol_vault.vault.mint)]
+    pub creator_token_account: Account<'info, TokenAccount>,
+    
+    pub creator: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct BuybackFromOrderBook<'info> {
+    #[account(
+        mut,
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump,
+        has_one = creator @ BondError::Unauthorized
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        seeds = [b"order_book", bond.key().as_ref()],
+        bump
+    )]
+    pub order_book: AccountLoader<'info, OrderBook>,
+    
+    #[account(
+        mut,
+        seeds = [b"holder_bonds", bond.key().as_ref(), seller_holder_bonds.holder.as_ref()],
+        bump = seller_holder_bonds.bump
+    )]
+    pub seller_holder_bonds: Account<'info, HolderBonds>,
+    
+    pub seller_holder_page: HolderPageAccount<'info>,
+    
+    #[account(mut)]
+    pub seller_quote_account: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub creator_token_account: Account<'info, TokenAccount>,
+    
+    pub creator: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigureCircuitBreaker<'info> {
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump,
+        has_one = creator @ BondError::Unauthorized
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ResumeTrading<'info> {
+    #[account(
+        seeds = [b"bonds_state"],
+        bump = bonds_state.bump,
+        has_one = authority @ BondError::Unauthorized
+    )]
+    pub bonds_state: Account<'info, BondsState>,
+    
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", bond.key().as_ref()],
+        bump = market.bump,
+        has_one = bond @ BondError::MarketBondMismatch
+    )]
+    pub market: Account<'info, Market>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(incident_id: u64)]
+pub struct OpenInsuranceIncident<'info> {
+    #[account(
+        seeds = [b"bonds_state"],
+        bump = bonds_state.bump,
+        has_one = authority @ BondError::Unauthorized
+    )]
+    pub bonds_state: Account<'info, BondsState>,
+    
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + size_of::<InsuranceIncident>(),
+        seeds = [b"incident", bond.key().as_ref(), incident_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub incident: Account<'info, InsuranceIncident>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CompensateHolder<'info> {
+    #[account(
+        seeds = [b"bonds_state"],
+        bump = bonds_state.bump,
+        has_one = authority @ BondError::Unauthorized
+    )]
+    pub bonds_state: Account<'info, BondsState>,
+    
+    #[account(
+        mut,
+        seeds = [b"fee_ledger", fee_ledger.mint.as_ref()],
+        bump = fee_ledger.bump
+    )]
+    pub fee_ledger: Account<'info, FeeLedger>,
+    
+    #[account(
+        seeds = [b"bond", bond.id.to_le_bytes().as_ref()],
+        bump = bond.bump
+    )]
+    pub bond: Account<'info, Bond>,
+    
+    #[account(
+        mut,
+        seeds = [b"incident", bond.key().as_ref(), incident.incident_id.to_le_bytes().as_ref()],
+        bump = incident.bump
+    )]
+    pub incident: Account<'info, InsuranceIncident>,
+    
+    #[account(
+        seeds = [b"holder_bonds", bond.key().as_ref(), holder_bonds.holder.as_ref()],
+        bump = holder_bonds.bump
+    )]
+    pub holder_bonds: Account<'info, HolderBonds>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + size_of::<InsuranceClaim>(),
+        seeds = [b"insurance_claim", incident.key().as_ref(), holder_bonds.holder.as_ref()],
+        bump
+    )]
+    pub insurance_claim: Account<'info, InsuranceClaim>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimInsurance<'info> {
+    #[account(mut)]
+    pub incident: Account<'info, InsuranceIncident>,
+    
+    #[account(
+        mut,
+        seeds = [b"insurance_claim", incident.key().as_ref(), holder.key().as_ref()],
+        bump = insurance_claim.bump,
+        has_one = holder @ BondError::Unauthorized,
+        constraint = insurance_claim.mint == protocol_vault.vault.mint @ BondError::InvalidVault
+    )]
+    pub insurance_claim: Account<'info, InsuranceClaim>,
+    
+    pub protocol_vault: ProtocolVault<'info>,
+    
+    #[account(mut, constraint = holder_token_account.mint == protocol_vault.vault.mint)]
+    pub holder_token_account: Account<'info, TokenAccount>,
+    
+    pub holder: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+// A page of a bond's holder index. It may not exist yet, so it is passed unchecked and
+// credit_holder/debit_holder verify its address and owner before touching it.
+#[derive(Accounts)]
+pub struct HolderPageAccount<'info> {
+    /// CHECK: Holder index page, validated by credit_holder and debit_holder
+    #[account(mut)]
+    pub page: UncheckedAccount<'info>,
+}
+
+// The protocol vault for one quote mint, with the PDA that signs transfers out of it
+#[derive(Accounts)]
+pub struct ProtocolVault<'info> {
+    #[account(
+        mut,
+        constraint = vault.owner == vault_authority.key() @ BondError::InvalidVault
+    )]
+    pub vault: Account<'info, TokenAccount>,
+    
+    /// CHECK: PDA that controls the vault
+    #[account(
+        seeds = [b"vault_authority"],
+        bump
+    )]
+    pub vault_authority: AccountInfo<'info>,
+}
+
+impl<'info> ProtocolVault<'info> {
+    pub fn transfer_out(
+        &self,
+        bumps: &ProtocolVaultBumps,
+        to: AccountInfo<'info>,
+        token_program: &Program<'info, Token>,
+        amount: u64,
+    ) -> Result<()> {
+        let vault_authority_seeds = &[
+            b"vault_authority".as_ref(),
+            &[bumps.vault_authority]
+        ];
+        let signer = &[&vault_authority_seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: self.vault.to_account_info(),
+            to,
+            authority: self.vault_authority.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer);
+        token::transfer(cpi_ctx, amount)
+    }
+}
+
+#[account]
+pub struct BondsState {
+    pub authority: Pubkey,
+    pub bond_counter: u64,
+    pub graduation_threshold: u64,
+    pub bump: u8,
+    // Fields below were added after deployment; see migrate_bonds_state
+    pub trade_fee_bps: u16,
+    pub referral_share_bps: u16,
+    pub insurance_share_bps: u16,
+}
+
+/// Layout of `BondsState` before the fee fields, read only by `migrate_bonds_state`.
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyBondsState {
+    pub authority: Pubkey,
+    pub bond_counter: u64,
+    pub graduation_threshold: u64,
+    pub bump: u8,
+}
+
+// Protocol fees and the insurance fund accrued in one quote mint, held in the vaults of that mint
+#[account]
+pub struct FeeLedger {
+    pub mint: Pubkey,
+    pub protocol_fees: u64,
+    pub insurance_fund: u64, // Earmarked for incident compensation
+    pub bump: u8,
+}
+
+impl BondsState {
+    pub fn trade_fee(&self, payment_amount: u64) -> u64 {
+        (payment_amount as u128 * self.trade_fee_bps as u128 / 10_000) as u64
+    }
+}
+
+#[account]
+pub struct Bond {
+    pub id: u64,
+    pub creator: Pubkey,
+    pub name: String,
+    pub description: String,
+    pub category: String,
+    pub metric: String,
+    pub metadata_uri: String,
+    pub total_supply: u64,
+    pub current_supply: u64,
+    pub price: u64,
+    pub oracle: Pubkey,
+    pub current_value: u64,
+    pub last_update: i64,
+    pub active: bool,
+    pub trading_mode: TradingMode,
+    pub trade_limits: TradeLimits,
+    pub presale_merkle_root: [u8; 32],
+    pub public_sale_start: i64,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum TradingMode {
+    Continuous,
+    BatchAuction,
+    CommitReveal,
+}
+
+// Zero means no limit
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct TradeLimits {
+    pub max_wallet_amount: u64,
+    pub max_trade_amount: u64,
+    pub launch_window_end: i64,
+    pub launch_max_wallet_amount: u64,
+    pub launch_max_trade_amount: u64,
+}
+
+impl TradeLimits {
+    pub const LEN: usize = 8 + 8 + 8 + 8 + 8;
+
+    // Returns the (wallet, per-buy) caps in force; launch caps can only tighten the regular ones
+    pub fn current_caps(&self, now: i64) -> (u64, u64) {
+        if now < self.launch_window_end {
+            (
+                Self::tighter(self.launch_max_wallet_amount, self.max_wallet_amount),
+                Self::tighter(self.launch_max_trade_amount, self.max_trade_amount),
+            )
+        } else {
+            (self.max_wallet_amount, self.max_trade_amount)
+        }
+    }
+
+    // Anti-whale limits cap buys only, so holders can always exit
+    pub fn check_buy(&self, now: i64, holding: u64, amount: u64) -> Result<()> {
+        let (_, max_trade_amount) = self.current_caps(now);
+        require!(
+            max_trade_amount == 0 || amount <= max_trade_amount,
+            BondError::TradeLimitExceeded
+        );
+        self.check_holding(now, holding.checked_add(amount).unwrap())
+    }
+
+    // Wallet cap for units arriving by any route, including transfers
+    pub fn check_holding(&self, now: i64, holding: u64) -> Result<()> {
+        let (max_wallet_amount, _) = self.current_caps(now);
+        require!(
+            max_wallet_amount == 0 || holding <= max_wallet_amount,
+            BondError::WalletLimitExceeded
+        );
+        Ok(())
+    }
+
+    // Units a wallet can still receive under the cap
+    pub fn holding_room(&self, now: i64, holding: u64) -> u64 {
+        match self.current_caps(now) {
+            (0, _) => u64::MAX,
+            (max_wallet_amount, _) => max_wallet_amount.saturating_sub(holding),
+        }
+    }
+
+    fn tighter(launch_cap: u64, cap: u64) -> u64 {
+        match (launch_cap, cap) {
+            (0, cap) => cap,
+            (launch_cap, 0) => launch_cap,
+            (launch_cap, cap) => launch_cap.min(cap),
+        }
+    }
+}
+
+impl Bond {
+    // Serialized size of every fixed-width field, including the discriminator
+    pub const FIXED_LEN: usize = 8 + // discriminator
+        8 + // id
+        32 + // creator
+        8 + // total_supply
+        8 + // current_supply
+        8 + // price
+        32 + // oracle
+        8 + // current_value
+        8 + // last_update
+        1 + // active
+        1 + // trading_mode
+        TradeLimits::LEN + // trade_limits
+        32 + // presale_merkle_root
+        8 + // public_sale_start
+        1; // bump
+
+    pub fn space(
+        name: &str,
+        description: &str,
+        category: &str,
+        metric: &str,
+        metadata_uri: &str,
+    ) -> usize {
+        Self::FIXED_LEN
+            + 4 + name.len()
+            + 4 + description.len()
+            + 4 + category.len()
+            + 4 + metric.len()
+            + 4 + metadata_uri.len()
+    }
+
+    // Buys need an allowlist proof until the public sale starts
+    pub fn presale_active(&self, now: i64) -> bool {
+        self.presale_merkle_root != [0; 32] && now < self.public_sale_start
+    }
+}
+
+fn validate_bond_metadata(
+    name: &str,
+    description: &str,
+    metric: &str,
+    metadata_uri: &str,
+) -> Result<()> {
+    require!(name.len() <= MAX_BOND_NAME_LEN, BondError::NameTooLong);
+    require!(
+        description.len() <= MAX_BOND_DESCRIPTION_LEN,
+        BondError::DescriptionTooLong
+    );
+    require!(metric.len() <= MAX_BOND_METRIC_LEN, BondError::MetricTooLong);
+    require!(
+        metadata_uri.len() <= MAX_BOND_METADATA_URI_LEN,
+        BondError::MetadataUriTooLong
+    );
+    Ok(())
+}
+
+#[account]
+pub struct Market {
+    pub bond: Pubkey,
+    pub total_volume: u64,
+    pub last_price: u64,
+    pub price_change_24h: i64,
+    pub market_cap: u64,
+    pub holder_count: u64,
+    pub holder_tail_page: u64,
+    pub holder_vacancies: u64, // Free slots left behind on pages before the tail
+    pub quote_mint: Pubkey, // Mint the price and reserve are quoted in, bound on first use
+    pub reserve: u64,
+    pub graduated: bool,
+    pub migrated: bool,
+    pub batch_epoch: u64,
+    pub batch_pending: bool,
+    pub auction_active: bool,
+    pub has_metric_history: bool, // Every metric update must then record into the history
+    pub governance_epoch: u64, // Bumped per proposal; holder balances snapshot against it
+    pub proposal_active: bool,
+    pub circuit_breaker: CircuitBreaker,
+    pub bump: u8,
+}
+
+impl Market {
+    // Market cap is measured on circulating units, so it grows as primary issuance sells out
+    pub fn refresh_market_cap(&mut self, bond: &Bond) {
+        let circulating_supply = bond.total_supply.checked_sub(bond.current_supply).unwrap();
+        self.market_cap = bond.price.checked_mul(circulating_supply).unwrap();
+    }
+
+    // Binds the market to the first quote mint it settles in and rejects any other
+    pub fn bind_quote_mint(&mut self, mint: Pubkey) -> Result<()> {
+        if self.quote_mint == Pubkey::default() {
+            self.quote_mint = mint;
+        }
+        require_keys_eq!(self.quote_mint, mint, BondError::QuoteMintMismatch);
+        Ok(())
+    }
+
+    pub fn has_graduated(&self, graduation_threshold: u64) -> bool {
+        self.graduated || (graduation_threshold > 0 && self.market_cap >= graduation_threshold)
+    }
+}
+
+#[account]
+pub struct CreatorBonds {
+    pub creator: Pubkey,
+    pub bond_count: u64,
+}
+
+/// Pre-paging layout of the `creator_bonds` account, read only by
+/// `migrate_creator_bonds`.
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyCreatorBonds {
+    pub creator: Pubkey,
+    pub bond_ids: Vec<u64>,
+}
+
+#[account]
+pub struct CreatorBondsPage {
+    pub creator: Pubkey,
+    pub page_index: u64,
+    pub bond_ids: Vec<u64>,
+}
+
+#[account]
+pub struct HolderBonds {
+    pub holder: Pubkey,
+    pub bond: Pubkey,
+    pub amount: u64,
+    pub locked: u64,
+    pub holder_page: u64,
+    pub snapshot_epoch: u64,
+    pub snapshot_amount: u64, // Balance as of the start of snapshot_epoch
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct CircuitBreaker {
+    pub window_slots: u64,
+    pub threshold_bps: u64, // Zero disables the breaker
+    pub cooldown_slots: u64, // Zero keeps a halt in place until manually resumed
+    pub bucket_ids: [u64; CIRCUIT_BREAKER_BUCKETS], // slot / bucket_slots() each bucket is counting
+    pub bucket_moves_bps: [u64; CIRCUIT_BREAKER_BUCKETS],
+    pub halted: bool,
+    pub halted_at_slot: u64,
+}
+
+impl CircuitBreaker {
+    pub fn is_halted(&self, slot: u64) -> bool {
+        self.halted && (self.cooldown_slots == 0 || slot < self.resume_slot())
+    }
+
+    pub fn resume_slot(&self) -> u64 {
+        self.halted_at_slot.saturating_add(self.cooldown_slots)
+    }
+
+    pub fn reset_window(&mut self) {
+        self.bucket_moves_bps = [0; CIRCUIT_BREAKER_BUCKETS];
+    }
+
+    // The window is split into buckets so it rolls forward a bucket at a time
+    fn bucket_slots(&self) -> u64 {
+        self.window_slots.div_ceil(CIRCUIT_BREAKER_BUCKETS as u64).max(1)
+    }
+
+    // Total move over the buckets that still fall inside the window ending at `slot`
+    pub fn window_move_bps(&self, slot: u64) -> u64 {
+        let current = slot / self.bucket_slots();
+        self.bucket_ids
+            .iter()
+            .zip(self.bucket_moves_bps.iter())
+            .filter(|(id, _)| **id <= current && current - **id < CIRCUIT_BREAKER_BUCKETS as u64)
+            .fold(0u64, |total, (_, moves)| total.saturating_add(*moves))
+    }
+
+    // Adds a price move to the rolling window, returning true if it trips a halt
+    pub fn record(&mut self, old_price: u64, new_price: u64, slot: u64) -> bool {
+        if self.threshold_bps == 0 || old_price == 0 {
+            return false;
+        }
+        if self.halted && !self.is_halted(slot) {
+            self.halted = false;
+        }
+
+        let bucket_id = slot / self.bucket_slots();
+        let index = (bucket_id % CIRCUIT_BREAKER_BUCKETS as u64) as usize;
+        if self.bucket_ids[index] != bucket_id {
+            self.bucket_ids[index] = bucket_id;
+            self.bucket_moves_bps[index] = 0;
+        }
+        let move_bps = (old_price.abs_diff(new_price) as u128 * 10_000 / old_price as u128) as u64;
+        self.bucket_moves_bps[index] = self.bucket_moves_bps[index].saturating_add(move_bps);
+
+        if !self.halted && self.window_move_bps(slot) >= self.threshold_bps {
+            self.halted = true;
+            self.halted_at_slot = slot;
+            self.reset_window();
+            return true;
+        }
+        false
+    }
+}
+
+impl HolderBonds {
+    // Records the pre-change balance the first time it moves within a governance epoch
+    pub fn snapshot(&mut self, governance_epoch: u64) {
+        if self.snapshot_epoch < governance_epoch {
+            self.snapshot_amount = self.amount;
+            self.snapshot_epoch = governance_epoch;
+        }
+    }
+
+    // A balance untouched since the epoch began is still the snapshot balance
+    pub fn voting_weight(&self, governance_epoch: u64) -> u64 {
+        if self.snapshot_epoch == governance_epoch {
+            self.snapshot_amount
+        } else {
+            self.amount
+        }
+    }
+
+    // Units not escrowed by open orders
+    pub fn available(&self) -> u64 {
+        self.amount.checked_sub(self.locked).unwrap()
+    }
+}
+
+#[account]
+pub struct HolderPage {
+    pub bond: Pubkey,
+    pub page_index: u64,
+    pub holders: Vec<Pubkey>,
+}
+
+impl HolderPage {
+    pub const SPACE: usize = 8 + size_of::<HolderPage>() +
+        (32 * HOLDER_PAGE_SIZE); // holders: Vec<Pubkey> (max HOLDER_PAGE_SIZE holders)
+}
+
+// Settles a trade against the protocol vault at the bond's current price
+fn execute_trade<'info>(
+    accounts: &mut TradeBond<'info>,
+    bumps: &TradeBondBumps,
+    amount: u64,
+    is_buy: bool,
+) -> Result<()> {
+    let bond = &mut accounts.bond;
+    let market = &mut accounts.market;
+    check_trading_allowed(bond, market)?;
+    require!(!market.auction_active, BondError::DutchAuctionActive);
+    market.bind_quote_mint(accounts.protocol_vault.vault.mint)?;
+
+    let fee_ledger = &mut accounts.fee_ledger;
+    fee_ledger.mint = accounts.protocol_vault.vault.mint;
+    fee_ledger.bump = bumps.fee_ledger;
+    let graduation_threshold = accounts.bonds_state.graduation_threshold;
+    require!(
+        !market.has_graduated(graduation_threshold),
+        BondError::BondGraduated
+    );
+
+    let holder_bonds = &mut accounts.holder_bonds;
+    holder_bonds.holder = accounts.buyer.key();
+    holder_bonds.bond = bond.key();
+    holder_bonds.bump = bumps.holder_bonds;
+    
+    if is_buy {
+        require!(
+            amount <= bond.current_supply,
+            BondError::InsufficientSupply
+        );
+        bond.trade_limits
+            .check_buy(Clock::get()?.unix_timestamp, holder_bonds.amount, amount)?;
+
+        // Transfer payment plus the trading fee
+        let payment_amount = amount.checked_mul(bond.price).unwrap();
+        let fee_amount = accounts.bonds_state.trade_fee(payment_amount);
+        let cpi_accounts = Transfer {
+            from: accounts.buyer_token_account.to_account_info(),
+            to: accounts.protocol_vault.vault.to_account_info(),
+            authority: accounts.buyer.to_account_info(),
+        };
+        let cpi_program = accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, payment_amount.checked_add(fee_amount).unwrap())?;
+        distribute_trade_fee(
+            &accounts.bonds_state,
+            &mut accounts.fee_ledger,
+            accounts.referrer_stats.as_deref_mut(),
+            payment_amount,
+            fee_amount,
+        );
+
+        // Update bond state
+        bond.current_supply = bond.current_supply.checked_sub(amount).unwrap();
+        credit_holder(
+            market,
+            holder_bonds,
+            &accounts.holder_page.page,
+            &accounts.buyer,
+            &accounts.system_program,
+            amount,
+        )?;
+        
+        // Update market data
+        market.total_volume = market.total_volume.checked_add(payment_amount).unwrap();
+        market.reserve = market.reserve.checked_add(payment_amount).unwrap();
+    } else {
+        // Selling bonds
+        require!(
+            amount <= holder_bonds.available(),
+            BondError::InsufficientBonds
+        );
+
+        // Calculate payment, with the trading fee withheld from the seller
+        let payment_amount = amount.checked_mul(bond.price).unwrap();
+        let fee_amount = accounts.bonds_state.trade_fee(payment_amount);
+        
+        // Transfer payment from vault to seller
+        accounts.protocol_vault.transfer_out(
+            &bumps.protocol_vault,
+            accounts.seller_token_account.to_account_info(),
+            &accounts.token_program,
+            payment_amount.checked_sub(fee_amount).unwrap(),
+        )?;
+        distribute_trade_fee(
+            &accounts.bonds_state,
+            &mut accounts.fee_ledger,
+            accounts.referrer_stats.as_deref_mut(),
+            payment_amount,
+            fee_amount,
+        );
+
+        // Update bond state
+        bond.current_supply = bond.current_supply.checked_add(amount).unwrap();
+        debit_holder(market, holder_bonds, &accounts.holder_page.page, amount)?;
+        
+        // Update market data. The fee is carved out of the reserve: the seller gets the
+        // payment less the fee and the fee stays in the vault on the fee ledger
+        market.total_volume = market.total_volume.checked_add(payment_amount).unwrap();
+        market.reserve = market
+            .reserve
+            .checked_sub(payment_amount)
+            .ok_or(BondError::InsufficientLiquidity)?;
+    }
+
+    // Primary issuance stops once the bond is large enough to graduate to a pool
+    market.refresh_market_cap(bond);
+    market.graduated = market.has_graduated(graduation_threshold);
+
+    emit!(BondTradedEvent {
+        bond_id: bond.id,
+        trader: accounts.buyer.key(),
+        amount,
+        price: bond.price,
+        is_buy,
+    });
+
+    Ok(())
+}
+
+// Credits units to a position, adding the holder to the bond's index on their first units.
+// New holders fill a freed slot on any earlier page first; a fresh tail page is only
+// opened once no such vacancies remain.
+fn credit_holder<'info>(
+    market: &mut Market,
+    holder_bonds: &mut HolderBonds,
+    holder_page: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    holder_bonds.snapshot(market.governance_epoch);
+
+    if holder_bonds.amount == 0 && amount > 0 {
+        let mut page = if holder_page.data_is_empty() {
+            let page_index = market.holder_tail_page;
+            require!(market.holder_vacancies == 0, BondError::HolderVacancyAvailable);
+            let bump = check_holder_page(holder_page, &market.bond, page_index)?;
+            create_holder_page(holder_page, payer, system_program, &market.bond, page_index, bump)?;
+            HolderPage {
+                bond: market.bond,
+                page_index,
+                holders: Vec::new(),
+            }
+        } else {
+            let page = HolderPage::try_deserialize(&mut &holder_page.try_borrow_data()?[..])?;
+            check_holder_page(holder_page, &market.bond, page.page_index)?;
+            require!(page.page_index <= market.holder_tail_page, BondError::InvalidHolderPage);
+            require!(page.holders.len() < HOLDER_PAGE_SIZE, BondError::HolderPageFull);
+            page
+        };
+
+        if page.page_index < market.holder_tail_page {
+            market.holder_vacancies = market.holder_vacancies.checked_sub(1).unwrap();
+        }
+
+        page.holders.push(holder_bonds.holder);
+        holder_bonds.holder_page = page.page_index;
+        market.holder_count = market.holder_count.checked_add(1).unwrap();
+
+        // New holders go to the next page once the tail is full
+        if page.page_index == market.holder_tail_page && page.holders.len() >= HOLDER_PAGE_SIZE {
+            market.holder_tail_page = market.holder_tail_page.checked_add(1).unwrap();
+        }
+
+        page.try_serialize(&mut &mut holder_page.try_borrow_mut_data()?[..])?;
+    }
+
+    holder_bonds.amount = holder_bonds.amount.checked_add(amount).unwrap();
+    Ok(())
+}
+
+// Debits units from a position, removing the holder from the bond's index once it is empty.
+// The slot is swap-removed so pages stay dense, and a slot freed before the tail is
+// recorded as a vacancy for the next new holder.
+fn debit_holder(
+    market: &mut Market,
+    holder_bonds: &mut HolderBonds,
+    holder_page: &AccountInfo,
+    amount: u64,
+) -> Result<()> {
+    require!(amount <= holder_bonds.available(), BondError::InsufficientBonds);
+    holder_bonds.snapshot(market.governance_epoch);
+    holder_bonds.amount = holder_bonds.amount.checked_sub(amount).unwrap();
+
+    if holder_bonds.amount == 0 && amount > 0 {
+        check_holder_page(holder_page, &market.bond, holder_bonds.holder_page)?;
+        require!(!holder_page.data_is_empty(), BondError::InvalidHolderPage);
+
+        let mut page = HolderPage::try_deserialize(&mut &holder_page.try_borrow_data()?[..])?;
+        let position = page
+            .holders
+            .iter()
+            .position(|holder| *holder == holder_bonds.holder)
+            .ok_or(BondError::InvalidHolderPage)?;
+        page.holders.swap_remove(position);
+        market.holder_count = market.holder_count.checked_sub(1).unwrap();
+        if page.page_index < market.holder_tail_page {
+            market.holder_vacancies = market.holder_vacancies.checked_add(1).unwrap();
+        }
+
+        page.try_serialize(&mut &mut holder_page.try_borrow_mut_data()?[..])?;
+    }
+
+    Ok(())
+}
+
+fn check_holder_page(holder_page: &AccountInfo, bond: &Pubkey, page_index: u64) -> Result<u8> {
+    let (expected, bump) = Pubkey::find_program_address(
+        &[b"holder_page", bond.as_ref(), page_index.to_le_bytes().as_ref()],
+        &crate::ID,
+    );
+    require_keys_eq!(holder_page.key(), expected, BondError::InvalidHolderPage);
+    require!(
+        holder_page.data_is_empty() || holder_page.owner == &crate::ID,
+        BondError::InvalidHolderPage
+    );
+    Ok(bump)
+}
+
+fn create_holder_page<'info>(
+    holder_page: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    bond: &Pubkey,
+    page_index: u64,
+    bump: u8,
+) -> Result<()> {
+    let page_index_bytes = page_index.to_le_bytes();
+    let seeds = &[
+        b"holder_page".as_ref(),
+        bond.as_ref(),
+        page_index_bytes.as_ref(),
+        &[bump],
+    ];
+    create_program_account(holder_page, payer, system_program, &[&seeds[..]], HolderPage::SPACE)
+}
+
+// Creates a program-owned account at a PDA, claiming it if someone pre-funded the address
+fn create_program_account<'info>(
+    account: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    signer: &[&[&[u8]]],
+    space: usize,
+) -> Result<()> {
+    let rent = Rent::get()?.minimum_balance(space);
+    let current_lamports = account.lamports();
+
+    if current_lamports == 0 {
+        let cpi_accounts = anchor_lang::system_program::CreateAccount {
+            from: payer.clone(),
+            to: account.clone(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(system_program.clone(), cpi_accounts, signer);
+        anchor_lang::system_program::create_account(
+            cpi_ctx,
+            rent,
+            space as u64,
+            &crate::ID,
+        )?;
+    } else {
+        // Someone pre-funded the address, so top it up and claim it instead
+        let top_up = rent.saturating_sub(current_lamports);
+        if top_up > 0 {
+            let cpi_accounts = anchor_lang::system_program::Transfer {
+                from: payer.clone(),
+                to: account.clone(),
+            };
+            let cpi_ctx = CpiContext::new(system_program.clone(), cpi_accounts);
+            anchor_lang::system_program::transfer(cpi_ctx, top_up)?;
+        }
+
+        let cpi_accounts = anchor_lang::system_program::Allocate {
+            account_to_allocate: account.clone(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(system_program.clone(), cpi_accounts, signer);
+        anchor_lang::system_program::allocate(cpi_ctx, space as u64)?;
+
+        let cpi_accounts = anchor_lang::system_program::Assign {
+            account_to_assign: account.clone(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(system_program.clone(), cpi_accounts, signer);
+        anchor_lang::system_program::assign(cpi_ctx, &crate::ID)?;
+    }
+
+    Ok(())
+}
+
+#[account]
+pub struct Pool {
+    pub bond: Pubkey,
+    pub quote_mint: Pubkey,
+    pub quote_vault: Pubkey,
+    pub lp_mint: Pubkey,
+    pub bond_reserve: u64,
+    pub quote_reserve: u64,
+    pub lp_supply: u64,
+    pub fee_bps: u16,
+    pub bump: u8,
+}
+
+impl Pool {
+    // LP tokens owed for a deposit, proportional to the smaller side once the pool is seeded
+    pub fn liquidity_for(&self, bond_amount: u64, quote_amount: u64) -> u64 {
+        if self.lp_supply == 0 {
+            return integer_sqrt(bond_amount as u128 * quote_amount as u128) as u64;
+        }
+        if self.bond_reserve == 0 || self.quote_reserve == 0 {
+            return 0;
+        }
+
+        let from_bond = bond_amount as u128 * self.lp_supply as u128 / self.bond_reserve as u128;
+        let from_quote = quote_amount as u128 * self.lp_supply as u128 / self.quote_reserve as u128;
+        from_bond.min(from_quote) as u64
+    }
+
+    // Splits a graduating bond's reserve and unsold units into the units and quote that
+    // can be deposited together: at the pool's ratio once it is seeded, or at the bond
+    // price while it is empty
+    pub fn graduation_deposit(&self, price: u64, quote_amount: u64, unsold_amount: u64) -> (u64, u64) {
+        if self.lp_supply == 0 || self.bond_reserve == 0 || self.quote_reserve == 0 {
+            let bond_amount = match quote_amount.checked_div(price) {
+                Some(units) => units.min(unsold_amount),
+                None => unsold_amount,
+            };
+            return (bond_amount, quote_amount);
+        }
+
+        let wanted = quote_amount as u128 * self.bond_reserve as u128 / self.quote_reserve as u128;
+        if wanted <= unsold_amount as u128 {
+            return (wanted as u64, quote_amount);
+        }
+        let paired_quote = unsold_amount as u128 * self.quote_reserve as u128 / self.bond_reserve as u128;
+        (unsold_amount, paired_quote as u64)
+    }
+
+    // Constant-product output for an input net of the pool fee
+    pub fn swap_output(&self, amount_in: u64, is_buy: bool) -> u64 {
+        let (reserve_in, reserve_out) = if is_buy {
+            (self.quote_reserve, self.bond_reserve)
+        } else {
+            (self.bond_reserve, self.quote_reserve)
+        };
+        if reserve_in == 0 || reserve_out == 0 {
+            return 0;
+        }
+
+        let amount_in_after_fee = amount_in as u128 * (10000 - self.fee_bps as u128) / 10000;
+        (reserve_out as u128 * amount_in_after_fee / (reserve_in as u128 + amount_in_after_fee)) as u64
+    }
+}
+
+fn integer_sqrt(value: u128) -> u128 {
+    if value < 2 {
+        return value;
+    }
+
+    let mut x = value;
+    let mut y = (x + value / x) / 2;
+    while y < x {
+        x = y;
+        y = (x + value / x) / 2;
+    }
+    x
+}
+
+#[account(zero_copy)]
+pub struct OrderBook {
+    pub bond: Pubkey,
+    pub quote_mint: Pubkey,
+    pub quote_vault: Pubkey,
+    pub next_order_id: u64,
+    pub order_count: u64,
+    pub min_order_value: u64, // Minimum price * amount per order, in quote units
+    pub bump: u8,
+    pub _padding: [u8; 7],
+    pub orders: [Order; MAX_ORDERS],
+}
+
+#[zero_copy]
+pub struct Order {
+    pub owner: Pubkey,
+    pub order_id: u64, // Monotonic, so a lower id means earlier time priority
+    pub price: u64,
+    pub amount: u64, // Remaining unfilled amount, zero for a free slot
+    pub side: u8,
+    pub _padding: [u8; 7],
+}
+
+impl Order {
+    pub const BID: u8 = 0;
+    pub const ASK: u8 = 1;
+
+    pub fn is_bid(&self) -> bool {
+        self.side == Self::BID
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.amount > 0
+    }
+}
+
+impl OrderBook {
+    pub fn insert(&mut self, owner: Pubkey, is_bid: bool, price: u64, amount: u64) -> Result<u64> {
+        let slot = self
+            .orders
+            .iter()
+            .position(|order| !order.is_open())
+            .ok_or(BondError::OrderBookFull)?;
+
+        let order_id = self.next_order_id;
+        self.orders[slot] = Order {
+            owner,
+            order_id,
+            price,
+            amount,
+            side: if is_bid { Order::BID } else { Order::ASK },
+            _padding: [0; 7],
+        };
+        self.next_order_id = self.next_order_id.checked_add(1).unwrap();
+        self.order_count = self.order_count.checked_add(1).unwrap();
+        Ok(order_id)
+    }
+
+    pub fn find(&self, order_id: u64) -> Option<usize> {
+        self.orders
+            .iter()
+            .position(|order| order.is_open() && order.order_id == order_id)
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        self.orders[index] = bytemuck::Zeroable::zeroed();
+        self.order_count = self.order_count.checked_sub(1).unwrap();
+    }
+
+    // Fills an order, closing it once the remainder is worth less than the minimum.
+    // Returns the dust amount closed out, which the caller releases to the owner.
+    pub fn fill(&mut self, index: usize, amount: u64) -> u64 {
+        let order = &mut self.orders[index];
+        order.amount = order.amount.checked_sub(amount).unwrap();
+        let dust = if order.price.checked_mul(order.amount).unwrap() < self.min_order_value {
+            order.amount
+        } else {
+            0
+        };
+        if dust > 0 || !self.orders[index].is_open() {
+            self.remove(index);
+        }
+        dust
+    }
+
+    // Highest price first, then earliest order
+    pub fn best_bid(&self) -> Option<usize> {
+        self.orders
+            .iter()
+            .enumerate()
+            .filter(|(_, order)| order.is_open() && order.is_bid())
+            .max_by(|(_, a), (_, b)| a.price.cmp(&b.price).then(b.order_id.cmp(&a.order_id)))
+            .map(|(index, _)| index)
+    }
+
+    // Lowest price first, then earliest order
+    pub fn best_ask(&self) -> Option<usize> {
+        self.orders
+            .iter()
+            .enumerate()
+            .filter(|(_, order)| order.is_open() && !order.is_bid())
+            .min_by(|(_, a), (_, b)| a.price.cmp(&b.price).then(a.order_id.cmp(&b.order_id)))
+            .map(|(index, _)| index)
+    }
+
+    pub fn crosses_own_order(&self, owner: &Pubkey, is_bid: bool, price: u64) -> bool {
+        self.orders.iter().any(|order| {
+            order.is_open()
+                && order.owner == *owner
+                && order.is_bid() != is_bid
+                && if is_bid { order.price <= price } else { order.price >= price }
+        })
+    }
+}
+
+#[account]
+pub struct BatchAuction {
+    pub bond: Pubkey,
+    pub epoch: u64,
+    pub clearing_price: u64,
+    pub buy_fill_bps: u64,
+    pub sell_fill_bps: u64,
+    pub finalized: bool,
+    pub bump: u8,
+    pub orders: Vec<BatchOrder>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct BatchOrder {
+    pub owner: Pubkey,
+    pub is_buy: bool,
+    pub amount: u64,
+    pub limit_price: u64,
+    pub settled: bool,
+}
+
+impl BatchAuction {
+    // Units an order receives (buys) or gives up (sells) at the clearing price
+    pub fn filled_amount(
+        order: &BatchOrder,
+        clearing_price: u64,
+        buy_fill_bps: u64,
+        sell_fill_bps: u64,
+    ) -> u64 {
+        if order.is_buy {
+            if order.limit_price < clearing_price {
+                return 0;
+            }
+            (order.amount as u128 * buy_fill_bps as u128 / 10000) as u64
+        } else if order.limit_price <= clearing_price {
+            (order.amount as u128 * sell_fill_bps as u128 / 10000) as u64
+        } else {
+            0
+        }
+    }
+
+    // Share of the wanted amount that fits in the capacity, in basis points
+    fn fill_bps(capacity: u64, wanted: u64) -> u64 {
+        if wanted <= capacity {
+            10000
+        } else {
+            (capacity as u128 * 10000 / wanted as u128) as u64
+        }
+    }
+
+    fn total_filled(
+        &self,
+        is_buy: bool,
+        clearing_price: u64,
+        buy_fill_bps: u64,
+        sell_fill_bps: u64,
+    ) -> Result<u64> {
+        self.orders
+            .iter()
+            .filter(|order| order.is_buy == is_buy)
+            .try_fold(0u64, |total, order| {
+                total
+                    .checked_add(Self::filled_amount(order, clearing_price, buy_fill_bps, sell_fill_bps))
+                    .ok_or(BondError::InvalidAmount.into())
+            })
+    }
+
+    // Clears every order at the bond's current price against the vault. Sells are rationed
+    // pro-rata when the reserve plus this batch's buy payments cannot pay for them all, and
+    // buys when demand exceeds unsold supply plus the units sold into the batch.
+    pub fn finalize(&mut self, bond: &mut Bond, market: &mut Market) -> Result<(u64, u64)> {
+        let clearing_price = bond.price;
+        require!(clearing_price > 0, BondError::InvalidPrice);
+
+        let mut buy_demand: u64 = 0;
+        let mut sell_supply: u64 = 0;
+        for order in self.orders.iter() {
+            if order.is_buy && order.limit_price >= clearing_price {
+                buy_demand = buy_demand.checked_add(order.amount).ok_or(BondError::InvalidAmount)?;
+            } else if !order.is_buy && order.limit_price <= clearing_price {
+                sell_supply = sell_supply.checked_add(order.amount).ok_or(BondError::InvalidAmount)?;
+            }
+        }
+
+        let sell_capacity = (market.reserve / clearing_price).saturating_add(buy_demand);
+        let sell_fill_bps = Self::fill_bps(sell_capacity, sell_supply);
+        let total_sell_filled = self.total_filled(false, clearing_price, 0, sell_fill_bps)?;
+
+        let available = bond
+            .current_supply
+            .checked_add(total_sell_filled)
+            .ok_or(BondError::InvalidSupply)?;
+        let buy_fill_bps = Self::fill_bps(available, buy_demand);
+        let total_buy_filled = self.total_filled(true, clearing_price, buy_fill_bps, 0)?;
+
+        bond.current_supply = available
+            .checked_sub(total_buy_filled)
+            .ok_or(BondError::InvalidSupply)?;
+
+        // Rounding rationed buys down can leave a few sold units to the vault; if the
+        // reserve cannot pay for them the batch does not clear
+        let buy_value = total_buy_filled
+            .checked_mul(clearing_price)
+            .ok_or(BondError::InvalidAmount)?;
+        let sell_value = total_sell_filled
+            .checked_mul(clearing_price)
+            .ok_or(BondError::InvalidAmount)?;
+        market.reserve = market
+            .reserve
+            .checked_add(buy_value)
+            .ok_or(BondError::InvalidAmount)?
+            .checked_sub(sell_value)
+            .ok_or(BondError::InsufficientReserve)?;
+        market.total_volume = market
+            .total_volume
+            .checked_add(buy_value)
+            .and_then(|volume| volume.checked_add(sell_value))
+            .ok_or(BondError::InvalidAmount)?;
+        market.refresh_market_cap(bond);
+
+        self.clearing_price = clearing_price;
+        self.buy_fill_bps = buy_fill_bps;
+        self.sell_fill_bps = sell_fill_bps;
+        self.finalized = true;
+
+        Ok((total_buy_filled, total_sell_filled))
+    }
+}
+
+#[account]
+pub struct TradeCommitment {
+    pub trader: Pubkey,
+    pub bond: Pubkey,
+    pub commitment: [u8; 32],
+    pub commit_slot: u64,
+    pub deposit: u64,
+    pub bump: u8,
+}
+
+#[account]
+pub struct PresaleAllocation {
+    pub buyer: Pubkey,
+    pub bond: Pubkey,
+    pub purchased: u64,
+    pub bump: u8,
+}
+
+// Sorted-pair merkle verification, so proofs don't need to encode left/right positions
+fn verify_merkle_proof(proof: &[[u8; 32]], root: [u8; 32], leaf: [u8; 32]) -> bool {
+    let computed = proof.iter().fold(leaf, |node, sibling| {
+        if node <= *sibling {
+            hashv(&[&node, sibling]).to_bytes()
+        } else {
+            hashv(&[sibling, &node]).to_bytes()
+        }
+    });
+    computed == root
+}
+
+#[account]
+pub struct DutchAuction {
+    pub bond: Pubkey,
+    pub tranche_amount: u64,
+    pub start_price: u64,
+    pub floor_price: u64,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub sold_amount: u64,
+    pub last_bid_price: u64,
+    pub clearing_price: u64,
+    pub cleared: bool,
+    pub bump: u8,
+}
+
+impl DutchAuction {
+    // Price decays linearly from start_price to floor_price over the auction
+    pub fn current_price(&self, now: i64) -> u64 {
+        if now <= self.start_time {
+            return self.start_price;
+        }
+        if now >= self.end_time {
+            return self.floor_price;
+        }
+
+        let elapsed = (now - self.start_time) as u128;
+        let duration = (self.end_time - self.start_time) as u128;
+        let decay = (self.start_price - self.floor_price) as u128 * elapsed / duration;
+        self.start_price - decay as u64
+    }
+}
+
+#[account]
+pub struct DutchBid {
+    pub bidder: Pubkey,
+    pub auction: Pubkey,
+    pub amount: u64,
+    pub paid: u64,
+    pub settled: bool,
+    pub bump: u8,
+}
+
+// Books the sold tranche at the clearing price, returns unsold units to primary supply
+// and opens regular trading from there
+fn clear_dutch_auction(
+    dutch_auction: &mut DutchAuction,
+    bond: &mut Bond,
+    market: &mut Market,
+    clearing_price: u64,
+) -> Result<()> {
+    dutch_auction.clearing_price = clearing_price;
+    dutch_auction.cleared = true;
+
+    let proceeds = dutch_auction
+        .sold_amount
+        .checked_mul(clearing_price)
+        .ok_or(BondError::InvalidAmount)?;
+    let unsold_amount = dutch_auction
+        .tranche_amount
+        .checked_sub(dutch_auction.sold_amount)
+        .ok_or(BondError::InvalidSupply)?;
+    bond.current_supply = bond
+        .current_supply
+        .checked_add(unsold_amount)
+        .ok_or(BondError::InvalidSupply)?;
+    bond.price = clearing_price;
+
+    market.last_price = clearing_price;
+    market.reserve = market.reserve.checked_add(proceeds).ok_or(BondError::InvalidAmount)?;
+    market.total_volume = market
+        .total_volume
+        .checked_add(proceeds)
+        .ok_or(BondError::InvalidAmount)?;
+    market.auction_active = false;
+    market.refresh_market_cap(bond);
+
+    emit!(DutchAuctionClearedEvent {
+        bond_id: bond.id,
+        clearing_price,
+        sold_amount: dutch_auction.sold_amount,
+    });
+
+    Ok(())
+}
+
+// Settlements credit units that were bid for earlier, so the wallet cap is applied here
+// too. Units past it go back to primary supply, and the buyer only pays for the rest.
+fn return_over_cap(
+    bond: &mut Bond,
+    market: &mut Market,
+    holding: u64,
+    amount: u64,
+    price: u64,
+) -> Result<u64> {
+    let room = bond.trade_limits.holding_room(Clock::get()?.unix_timestamp, holding);
+    let credited = amount.min(room);
+    let returned = amount - credited;
+    if returned > 0 {
+        bond.current_supply = bond.current_supply.checked_add(returned).unwrap();
+        market.reserve = market
+            .reserve
+            .checked_sub(returned.checked_mul(price).unwrap())
+            .unwrap();
+        market.refresh_market_cap(bond);
+    }
+    Ok(credited)
+}
+
+#[account]
+pub struct ReferrerStats {
+    pub referrer: Pubkey,
+    pub claimable: u64,
+    pub lifetime_volume: u64,
+    pub lifetime_rewards: u64,
+    pub bump: u8,
+}
+
+// Splits a trading fee between the referrer, if any, the insurance fund and the protocol
+fn distribute_trade_fee(
+    bonds_state: &BondsState,
+    fee_ledger: &mut FeeLedger,
+    referrer_stats: Option<&mut ReferrerStats>,
+    payment_amount: u64,
+    fee_amount: u64,
+) {
+    let mut protocol_amount = fee_amount;
+    if let Some(referrer_stats) = referrer_stats {
+        let reward = (fee_amount as u128 * bonds_state.referral_share_bps as u128 / 10_000) as u64;
+        referrer_stats.claimable = referrer_stats.claimable.checked_add(reward).unwrap();
+        referrer_stats.lifetime_rewards = referrer_stats.lifetime_rewards.checked_add(reward).unwrap();
+        referrer_stats.lifetime_volume = referrer_stats.lifetime_volume.checked_add(payment_amount).unwrap();
+        protocol_amount -= reward;
+    }
+
+    let insurance_amount =
+        (protocol_amount as u128 * bonds_state.insurance_share_bps as u128 / 10_000) as u64;
+    fee_ledger.insurance_fund = fee_ledger.insurance_fund.checked_add(insurance_amount).unwrap();
+    protocol_amount -= insurance_amount;
+    fee_ledger.protocol_fees = fee_ledger.protocol_fees.checked_add(protocol_amount).unwrap();
+}
+
+// Batch buys escrow their worst-case cost and the trading fee on it
+fn batch_escrow(bonds_state: &BondsState, amount: u64, limit_price: u64) -> u64 {
+    let cost = amount.checked_mul(limit_price).unwrap();
+    cost.checked_add(bonds_state.trade_fee(cost)).unwrap()
+}
+
+// Batch and Dutch settlements book the trading fee like a curve trade. The fee is capped
+// at what the escrow still holds, in case the rate went up after the order was placed.
+fn charge_settlement_fee(
+    bonds_state: &BondsState,
+    fee_ledger: &mut FeeLedger,
+    payment_amount: u64,
+    available: u64,
+) -> u64 {
+    let fee_amount = bonds_state.trade_fee(payment_amount).min(available);
+    distribute_trade_fee(bonds_state, fee_ledger, None, payment_amount, fee_amount);
+    fee_amount
+}
+
+#[account]
+pub struct LendingPool {
+    pub bond: Pubkey,
+    pub quote_mint: Pubkey,
+    pub quote_vault: Pubkey,
+    pub total_deposits: u64,
+    pub total_shares: u64,
+    pub total_borrowed: u64,
+    pub accrued_interest: u64, // Interest owed by borrowers, already counted in total_deposits
+    pub bad_debt: u64, // Cumulative debt written off against lenders
+    pub last_accrual: i64,
+    pub ltv_bps: u16,
+    pub liquidation_threshold_bps: u16,
+    pub interest_rate_bps: u16,
+    pub bump: u8,
+}
+
+impl LendingPool {
+    pub fn shares_for(&self, amount: u64) -> u64 {
+        if self.total_shares == 0 || self.total_deposits == 0 {
+            return amount;
+        }
+        (amount as u128 * self.total_shares as u128 / self.total_deposits as u128) as u64
+    }
+
+    pub fn value_of(&self, shares: u64) -> u64 {
+        (shares as u128 * self.total_deposits as u128 / self.total_shares as u128) as u64
+    }
+
+    // Interest accrues to lenders as it is earned, so share prices already include it
+    // before any repayment and late depositors can't capture it
+    pub fn accrue(&mut self, now: i64) {
+        if self.last_accrual > 0 && now > self.last_accrual {
+            let elapsed = (now - self.last_accrual) as u128;
+            let interest = (self.total_borrowed as u128 * self.interest_rate_bps as u128 * elapsed
+                / (10_000 * SECONDS_PER_YEAR as u128)) as u64;
+            self.total_deposits = self.total_deposits.checked_add(interest).unwrap();
+            self.accrued_interest = self.accrued_interest.checked_add(interest).unwrap();
+        }
+        self.last_accrual = now;
+    }
+
+    // Repaid interest settles the accrued receivable, repaid principal frees up the pool
+    pub fn book_repayment(&mut self, interest_paid: u64, principal_paid: u64) {
+        let settled = interest_paid.min(self.accrued_interest);
+        self.accrued_interest -= settled;
+        // Per-loan rounding can collect slightly more than the pool accrued
+        self.total_deposits = self.total_deposits.checked_add(interest_paid - settled).unwrap();
+        self.total_borrowed = self.total_borrowed.checked_sub(principal_paid).unwrap();
+    }
+
+    // Writes unrecoverable debt off the pool's value
+    pub fn write_off(&mut self, interest: u64, principal: u64) {
+        let interest = interest.min(self.accrued_interest);
+        self.accrued_interest -= interest;
+        self.total_borrowed = self.total_borrowed.checked_sub(principal).unwrap();
+        let loss = interest.checked_add(principal).unwrap();
+        self.total_deposits = self.total_deposits.saturating_sub(loss);
+        self.bad_debt = self.bad_debt.checked_add(loss).unwrap();
+    }
+}
+
+#[account]
+pub struct LenderDeposit {
+    pub lender: Pubkey,
+    pub lending_pool: Pubkey,
+    pub shares: u64,
+    pub bump: u8,
+}
+
+#[account]
+pub struct Loan {
+    pub borrower: Pubkey,
+    pub bond: Pubkey,
+    pub collateral: u64,
+    pub principal: u64,
+    pub interest: u64,
+    pub last_accrual: i64,
+    pub bump: u8,
+}
+
+impl Loan {
+    pub fn debt(&self) -> u64 {
+        self.principal.checked_add(self.interest).unwrap()
+    }
+
+    // Collateral valued at the bond's metric price, scaled by a bps factor
+    pub fn borrow_limit(&self, price: u64, factor_bps: u16) -> u64 {
+        (self.collateral as u128 * price as u128 * factor_bps as u128 / 10_000) as u64
+    }
+
+    // Collateral owed for repaying `repay_amount` at `price` plus the liquidation bonus
+    pub fn seizable_collateral(&self, repay_amount: u64, price: u64) -> u64 {
+        if price == 0 {
+            return self.collateral;
+        }
+        let collateral = repay_amount as u128 * (10_000 + LIQUIDATION_BONUS_BPS) as u128
+            / (10_000 * price as u128);
+        collateral.min(self.collateral as u128) as u64
+    }
+
+    // Simple interest on the outstanding principal since the last accrual
+    pub fn accrue(&mut self, interest_rate_bps: u16, now: i64) {
+        if self.last_accrual > 0 && now > self.last_accrual {
+            let elapsed = (now - self.last_accrual) as u128;
+            let interest = self.principal as u128 * interest_rate_bps as u128 * elapsed
+                / (10_000 * SECONDS_PER_YEAR as u128);
+            self.interest = self.interest.checked_add(interest as u64).unwrap();
+        }
+        self.last_accrual = now;
+    }
+
+    // Pays down interest first, then principal; returns (interest, principal) covered
+    pub fn apply_repayment(&mut self, amount: u64) -> (u64, u64) {
+        let interest_paid = amount.min(self.interest);
+        let principal_paid = (amount - interest_paid).min(self.principal);
+        self.interest -= interest_paid;
+        self.principal -= principal_paid;
+        (interest_paid, principal_paid)
+    }
+}
+
+#[account]
+pub struct StakingPool {
+    pub bond: Pubkey,
+    pub reward_mint: Pubkey,
+    pub reward_vault: Pubkey,
+    pub total_staked: u64,
+    pub acc_reward_per_share: u128,
+    pub reward_rate: u128, // Rewards streamed per second, scaled by REWARD_PRECISION
+    pub reward_duration: i64,
+    pub reward_end: i64,
+    pub last_update: i64,
+    pub unstake_cooldown: i64,
+    pub bump: u8,
+}
+
+impl StakingPool {
+    // Streams rewards emitted since the last update to current stakers. The stream
+    // pauses while nothing is staked, so no single early staker collects a backlog.
+    pub fn update_rewards(&mut self, now: i64) {
+        if self.total_staked == 0 {
+            if self.last_update < self.reward_end && now > self.last_update {
+                self.reward_end = self.reward_end.checked_add(now - self.last_update).unwrap();
+            }
+        } else {
+            let until = now.min(self.reward_end);
+            if until > self.last_update {
+                let elapsed = (until - self.last_update) as u128;
+                self.acc_reward_per_share = self
+                    .acc_reward_per_share
+                    .checked_add(self.reward_rate * elapsed / self.total_staked as u128)
+                    .unwrap();
+            }
+        }
+        self.last_update = self.last_update.max(now);
+    }
+
+    // Adds rewards to the stream, spreading them and any unstreamed remainder over a
+    // fresh reward_duration
+    pub fn fund(&mut self, amount: u64, now: i64) {
+        self.update_rewards(now);
+        let remaining = if now < self.reward_end {
+            self.reward_rate * (self.reward_end - now) as u128
+        } else {
+            0
+        };
+        self.reward_rate = (remaining + amount as u128 * REWARD_PRECISION)
+            / self.reward_duration as u128;
+        self.reward_end = now.checked_add(self.reward_duration).unwrap();
+    }
+}
+
+#[account]
+pub struct StakePosition {
+    pub staker: Pubkey,
+    pub staking_pool: Pubkey,
+    pub amount: u64,
+    pub reward_debt: u128,
+    pub pending_rewards: u64,
+    pub unstaking_amount: u64,
+    pub unstake_available_at: i64,
+    pub bump: u8,
+}
+
+impl StakePosition {
+    // Moves rewards earned since the last sync into pending_rewards
+    pub fn harvest(&mut self, acc_reward_per_share: u128) {
+        let accrued = self.amount as u128 * acc_reward_per_share / REWARD_PRECISION;
+        let earned = accrued.saturating_sub(self.reward_debt) as u64;
+        self.pending_rewards = self.pending_rewards.checked_add(earned).unwrap();
+    }
+
+    pub fn sync(&mut self, acc_reward_per_share: u128) {
+        self.reward_debt = self.amount as u128 * acc_reward_per_share / REWARD_PRECISION;
+    }
+}
+
+#[account]
+pub struct PredictionMarket {
+    pub bond: Pubkey,
+    pub creator: Pubkey,
+    pub collateral_mint: Pubkey,
+    pub collateral_vault: Pubkey,
+    pub yes_mint: Pubkey,
+    pub no_mint: Pubkey,
+    pub threshold: u64,
+    pub resolve_time: i64,
+    pub outstanding_sets: u64,
+    pub resolution_value: u64, // Metric value in effect at resolve_time
+    pub value_recorded: bool,
+    pub resolved: bool,
+    pub outcome_yes: bool,
+    pub bump: u8,
+}
+
+impl PredictionMarket {
+    // The first update after the resolve time passes in the reading it replaces, which was
+    // the value in effect at the resolve time; returns whether it was recorded
+    pub fn record_reading(&mut self, value: u64, timestamp: i64) -> bool {
+        if self.value_recorded || timestamp <= self.resolve_time {
+            return false;
+        }
+        self.resolution_value = value;
+        self.value_recorded = true;
+        true
+    }
+}
+
+#[account]
+pub struct PerpMarket {
+    pub bond: Pubkey,
+    pub collateral_mint: Pubkey,
+    pub collateral_vault: Pubkey,
+    pub max_leverage: u8,
+    pub maintenance_margin_bps: u16,
+    pub funding_rate_bps: u16,
+    pub long_open_interest: u64,
+    pub short_open_interest: u64,
+    // Funding paid per unit of size by longs to shorts, negative when shorts pay
+    pub cumulative_funding: i128,
+    pub last_funding_time: i64,
+    pub lp_capital: u64, // Counterparty capital funded by LPs, net of realized trader PnL
+    pub lp_shares: u64,
+    pub total_margin: u64, // Margin backing open positions
+    pub realized_pnl: i128, // Net PnL realized by traders against LP capital
+    pub bad_debt: u64, // Losses positions ran up beyond their margin, never collected
+    pub bump: u8,
+}
+
+impl PerpMarket {
+    pub fn shares_for(&self, amount: u64) -> u64 {
+        if self.lp_shares == 0 || self.lp_capital == 0 {
+            return amount;
+        }
+        (amount as u128 * self.lp_shares as u128 / self.lp_capital as u128) as u64
+    }
+
+    pub fn value_of(&self, shares: u64) -> u64 {
+        (shares as u128 * self.lp_capital as u128 / self.lp_shares as u128) as u64
+    }
+
+    // Notional of the long/short imbalance, which LP capital is on the other side of
+    pub fn net_exposure(&self, mark_price: u64) -> u128 {
+        let imbalance = self.long_open_interest.abs_diff(self.short_open_interest);
+        imbalance as u128 * mark_price as u128
+    }
+
+    // Releases a closed position's margin and pays `payout` out of it, drawing any
+    // excess from LP capital. Fails if LP capital can't cover the excess.
+    pub fn settle_position(&mut self, margin: u64, equity: i128, payout: u64) -> Result<()> {
+        self.total_margin = self.total_margin.checked_sub(margin).unwrap();
+        if payout > margin {
+            let shortfall = payout - margin;
+            require!(shortfall <= self.lp_capital, BondError::PerpMarketInsolvent);
+            self.lp_capital -= shortfall;
+        } else {
+            self.lp_capital = self.lp_capital.checked_add(margin - payout).unwrap();
+        }
+
+        self.realized_pnl = self
+            .realized_pnl
+            .checked_add(equity.max(0) - margin as i128)
+            .unwrap();
+        if equity < 0 {
+            self.bad_debt = self.bad_debt.checked_add(equity.unsigned_abs() as u64).unwrap();
+        }
+        Ok(())
+    }
+
+    // Funding scales with the open interest imbalance and accrues pro rata per interval
+    pub fn update_funding(&mut self, mark_price: u64, now: i64) {
+        let elapsed = now - self.last_funding_time;
+        let total_open_interest = self.long_open_interest as i128 + self.short_open_interest as i128;
+        if elapsed > 0 && total_open_interest > 0 {
+            let imbalance = self.long_open_interest as i128 - self.short_open_interest as i128;
+            let funding = mark_price as i128 * self.funding_rate_bps as i128 * imbalance * elapsed as i128
+                / (total_open_interest * 10_000 * FUNDING_INTERVAL_SECONDS as i128);
+            self.cumulative_funding = self.cumulative_funding.checked_add(funding).unwrap();
+        }
+        self.last_funding_time = now;
+    }
+
+    pub fn add_open_interest(&mut self, is_long: bool, size: u64) {
+        if is_long {
+            self.long_open_interest = self.long_open_interest.checked_add(size).unwrap();
+        } else {
+            self.short_open_interest = self.short_open_interest.checked_add(size).unwrap();
+        }
+    }
+
+    pub fn remove_open_interest(&mut self, is_long: bool, size: u64) {
+        if is_long {
+            self.long_open_interest = self.long_open_interest.checked_sub(size).unwrap();
+        } else {
+            self.short_open_interest = self.short_open_interest.checked_sub(size).unwrap();
+        }
+    }
+}
+
+#[account]
+pub struct PerpLpPosition {
+    pub provider: Pubkey,
+    pub perp_market: Pubkey,
+    pub shares: u64,
+    pub bump: u8,
+}
+
+#[account]
+pub struct PerpPosition {
+    pub trader: Pubkey,
+    pub perp_market: Pubkey,
+    pub is_long: bool,
+    pub size: u64,
+    pub entry_price: u64,
+    pub margin: u64,
+    pub funding_snapshot: i128,
+    pub bump: u8,
+}
+
+impl PerpPosition {
+    // Margin plus unrealized PnL, net of funding accrued since the position opened
+    pub fn equity(&self, mark_price: u64, cumulative_funding: i128) -> i128 {
+        let price_move = mark_price as i128 - self.entry_price as i128;
+        let funding = (cumulative_funding - self.funding_snapshot) * self.size as i128;
+        let (pnl, funding_paid) = if self.is_long {
+            (price_move * self.size as i128, funding)
+        } else {
+            (-price_move * self.size as i128, -funding)
+        };
+        self.margin as i128 + pnl - funding_paid
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct BasketConstituent {
+    pub bond: Pubkey,
+    pub weight_bps: u16,
+}
+
+#[account]
+pub struct Basket {
+    pub manager: Pubkey,
+    pub basket_id: u64,
+    pub share_mint: Pubkey,
+    pub constituents: Vec<BasketConstituent>,
+    pub bump: u8,
+}
+
+impl Basket {
+    pub const SPACE: usize = 8 + size_of::<Basket>() +
+        (size_of::<BasketConstituent>() * MAX_BASKET_CONSTITUENTS); // constituents (max MAX_BASKET_CONSTITUENTS)
+}
+
+// Weights must sum to 100%, and bonds the basket may still hold can only be zero-weighted, not dropped
+fn validate_basket_constituents(
+    constituents: &[BasketConstituent],
+    previous: &[BasketConstituent],
+) -> Result<()> {
+    require!(
+        !constituents.is_empty() && constituents.len() <= MAX_BASKET_CONSTITUENTS,
+        BondError::InvalidBasketConstituents
+    );
+
+    let mut total_weight: u32 = 0;
+    for (index, constituent) in constituents.iter().enumerate() {
+        require!(
+            constituents[..index].iter().all(|other| other.bond != constituent.bond),
+            BondError::InvalidBasketConstituents
+        );
+        total_weight += constituent.weight_bps as u32;
+    }
+    require!(total_weight == 10_000, BondError::InvalidBasketConstituents);
+
+    require!(
+        previous
+            .iter()
+            .all(|old| constituents.iter().any(|new| new.bond == old.bond)),
+        BondError::InvalidBasketConstituents
+    );
+
+    Ok(())
+}
+
+// Accounts per basket constituent in remaining_accounts, in constituent order: Bond, Market,
+// the basket's HolderBonds and holder page, then the counterparty's HolderBonds and holder page
+const BASKET_LEG_ACCOUNTS: usize = 6;
+
+struct BasketLeg<'info> {
+    bond: Account<'info, Bond>,
+    market: Account<'info, Market>,
+    basket: Pubkey,
+    counterparty: Pubkey,
+    basket_holder_bonds: &'info AccountInfo<'info>,
+    basket_holder_page: &'info AccountInfo<'info>,
+    counterparty_holder_bonds: &'info AccountInfo<'info>,
+    counterparty_holder_page: &'info AccountInfo<'info>,
+    basket_holdings: u64,
+}
+
+impl<'info> BasketLeg<'info> {
+    // Moves units between the basket and the counterparty, creating either position if needed
+    fn transfer(
+        &mut self,
+        from_basket: bool,
+        amount: u64,
+        payer: &AccountInfo<'info>,
+        system_program: &AccountInfo<'info>,
+    ) -> Result<()> {
+        let bond = self.bond.key();
+        let mut basket_position =
+            load_holder_bonds(self.basket_holder_bonds, &bond, &self.basket, payer, system_program)?;
+        let mut counterparty_position = load_holder_bonds(
+            self.counterparty_holder_bonds,
+            &bond,
+            &self.counterparty,
+            payer,
+            system_program,
+        )?;
+
+        let market = &mut self.market;
+        if from_basket {
+            debit_holder(market, &mut basket_position, self.basket_holder_page, amount)?;
+            credit_holder(
+                market,
+                &mut counterparty_position,
+                self.counterparty_holder_page,
+                payer,
+                system_program,
+                amount,
+            )?;
+        } else {
+            debit_holder(market, &mut counterparty_position, self.counterparty_holder_page, amount)?;
+            credit_holder(
+                market,
+                &mut basket_position,
+                self.basket_holder_page,
+                payer,
+                system_program,
+                amount,
+            )?;
+        }
+        self.basket_holdings = basket_position.amount;
+
+        basket_position.try_serialize(&mut &mut self.basket_holder_bonds.try_borrow_mut_data()?[..])?;
+        counterparty_position
+            .try_serialize(&mut &mut self.counterparty_holder_bonds.try_borrow_mut_data()?[..])?;
+        self.market.exit(&crate::ID)
+    }
+}
+
+fn load_basket_legs<'info>(
+    basket: &Account<'info, Basket>,
+    counterparty: &Pubkey,
+    accounts: &'info [AccountInfo<'info>],
+) -> Result<Vec<BasketLeg<'info>>> {
+    require!(
+        accounts.len() == basket.constituents.len() * BASKET_LEG_ACCOUNTS,
+        BondError::InvalidBasketAccounts
+    );
+
+    let mut legs = Vec::with_capacity(basket.constituents.len());
+    for (constituent, leg) in basket.constituents.iter().zip(accounts.chunks(BASKET_LEG_ACCOUNTS)) {
+        let bond = Account::<Bond>::try_from(&leg[0])?;
+        require_keys_eq!(bond.key(), constituent.bond, BondError::InvalidBasketAccounts);
+        let market = Account::<Market>::try_from(&leg[1])?;
+        require_keys_eq!(market.bond, bond.key(), BondError::InvalidBasketAccounts);
+        check_not_halted(&market)?;
+
+        for (holder_bonds, holder) in [(&leg[2], basket.key()), (&leg[4], *counterparty)] {
+            let (expected, _) = Pubkey::find_program_address(
+                &[b"holder_bonds", constituent.bond.as_ref(), holder.as_ref()],
+                &crate::ID,
+            );
+            require_keys_eq!(holder_bonds.key(), expected, BondError::InvalidBasketAccounts);
+        }
+
+        // A position that hasn't been created yet simply holds nothing
+        let basket_holdings = HolderBonds::try_deserialize(&mut &leg[2].try_borrow_data()?[..])
+            .map(|holder_bonds| holder_bonds.amount)
+            .unwrap_or(0);
+
+        legs.push(BasketLeg {
+            bond,
+            market,
+            basket: basket.key(),
+            counterparty: *counterparty,
+            basket_holder_bonds: &leg[2],
+            basket_holder_page: &leg[3],
+            counterparty_holder_bonds: &leg[4],
+            counterparty_holder_page: &leg[5],
+            basket_holdings,
+        });
+    }
+
+    Ok(legs)
+}
+
+fn basket_legs_nav(legs: &[BasketLeg]) -> Result<u64> {
+    legs.iter().try_fold(0u64, |nav, leg| {
+        leg.basket_holdings
+            .checked_mul(leg.bond.price)
+            .and_then(|value| nav.checked_add(value))
+            .ok_or(BondError::InvalidAmount.into())
+    })
+}
+
+// Loads a HolderBonds position from an unchecked account, creating it at its PDA if it doesn't exist yet
+fn load_holder_bonds<'info>(
+    holder_bonds: &AccountInfo<'info>,
+    bond: &Pubkey,
+    holder: &Pubkey,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<HolderBonds> {
+    if !holder_bonds.data_is_empty() {
+        require!(holder_bonds.owner == &crate::ID, BondError::InvalidBasketAccounts);
+        return HolderBonds::try_deserialize(&mut &holder_bonds.try_borrow_data()?[..]);
+    }
+
+    let (_, bump) = Pubkey::find_program_address(
+        &[b"holder_bonds", bond.as_ref(), holder.as_ref()],
+        &crate::ID,
+    );
+    let seeds = &[b"holder_bonds".as_ref(), bond.as_ref(), holder.as_ref(), &[bump]];
+    create_program_account(
+        holder_bonds,
+        payer,
+        system_program,
+        &[&seeds[..]],
+        8 + size_of::<HolderBonds>(),
+    )?;
+
+    Ok(HolderBonds {
+        holder: *holder,
+        bond: *bond,
+        amount: 0,
+        locked: 0,
+        holder_page: 0,
+        snapshot_epoch: 0,
+        snapshot_amount: 0,
+        bump,
+    })
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MetricComponentConfig {
+    pub name: String,
+    pub oracle: Pubkey,
+    pub weight_bps: u16,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MetricComponent {
+    pub name: String,
+    pub oracle: Pubkey,
+    pub weight_bps: u16,
+    pub base_value: u64,
+    pub current_value: u64,
+    pub last_update: i64,
+}
+
+#[account]
+pub struct CompositeMetric {
+    pub bond: Pubkey,
+    pub components: Vec<MetricComponent>,
+    pub bump: u8,
+}
+
+impl CompositeMetric {
+    pub const SPACE: usize = 8 + size_of::<CompositeMetric>() +
+        ((size_of::<MetricComponent>() + MAX_BOND_METRIC_LEN) * MAX_METRIC_COMPONENTS); // components (max MAX_METRIC_COMPONENTS)
+
+    // Stores an oracle's reading and returns the recomputed composite index
+    pub fn record(&mut self, oracle: &Pubkey, value: u64, now: i64) -> Result<u64> {
+        let component = self
+            .components
+            .iter_mut()
+            .find(|component| component.oracle == *oracle)
+            .ok_or(BondError::InvalidOracle)?;
+
+        // The first non-zero reading becomes the component's baseline
+        if component.base_value == 0 {
+            component.base_value = value;
+        }
+        component.current_value = value;
+        component.last_update = now;
+
+        Ok(self.index())
+    }
+
+    // Weighted sum of each component relative to its baseline, at par until it first reports
+    pub fn index(&self) -> u64 {
+        let weighted: u128 = self
+            .components
+            .iter()
+            .map(|component| {
+                let normalized = if component.base_value == 0 {
+                    COMPOSITE_INDEX_SCALE as u128
+                } else {
+                    component.current_value as u128 * COMPOSITE_INDEX_SCALE as u128
+                        / component.base_value as u128
+                };
+                normalized * component.weight_bps as u128
+            })
+            .sum();
+        (weighted / 10_000) as u64
+    }
+}
+
+// Ring buffer header; `capacity` MetricSamples follow it in the account data
+#[account(zero_copy)]
+pub struct MetricHistory {
+    pub bond: Pubkey,
+    pub min_interval: i64, // Readings closer together than this replace the latest sample
+    pub last_sample_time: i64,
+    pub capacity: u32,
+    pub len: u32,
+    pub head: u32, // Slot the next new sample is written to
+    pub bump: u8,
+    pub _padding: [u8; 3],
+}
+
+#[zero_copy]
+pub struct MetricSample {
+    pub timestamp: i64,
+    pub value: u64,
+}
+
+impl MetricHistory {
+    pub fn space(capacity: u32) -> usize {
+        8 + size_of::<MetricHistory>() + size_of::<MetricSample>() * capacity as usize
+    }
+
+    pub fn record(&mut self, samples: &mut [MetricSample], timestamp: i64, value: u64) {
+        let sample = MetricSample { timestamp, value };
+
+        // Downsample by keeping only the newest reading within each interval. The interval
+        // still starts at last_sample_time, but the sample carries the reading's own time.
+        if self.len > 0 && timestamp - self.last_sample_time < self.min_interval {
+            let latest = (self.head + self.capacity - 1) % self.capacity;
+            samples[latest as usize] = sample;
+            return;
+        }
+
+        samples[self.head as usize] = sample;
+        self.head = (self.head + 1) % self.capacity;
+        self.len = (self.len + 1).min(self.capacity);
+        self.last_sample_time = timestamp;
+    }
+
+    // Samples oldest first
+    pub fn chronological<'a>(&self, samples: &'a [MetricSample]) -> impl Iterator<Item = &'a MetricSample> {
+        let start = if self.len < self.capacity { 0 } else { self.head };
+        let capacity = self.capacity;
+        (0..self.len).map(move |offset| &samples[((start + offset) % capacity) as usize])
+    }
+
+    // The metric value in effect at a timestamp, if the history reaches back that far
+    pub fn value_at(&self, samples: &[MetricSample], timestamp: i64) -> Option<u64> {
+        self.chronological(samples)
+            .take_while(|sample| sample.timestamp <= timestamp)
+            .last()
+            .map(|sample| sample.value)
+    }
+}
+
+fn record_metric_sample(
+    metric_history: &AccountLoader<MetricHistory>,
+    timestamp: i64,
+    value: u64,
+) -> Result<()> {
+    // Borrow the raw data once so the header and trailing samples can be split
+    let account_info = metric_history.as_ref();
+    let mut data = account_info.try_borrow_mut_data()?;
+    let (header, samples) = data[8..].split_at_mut(size_of::<MetricHistory>());
+    let header: &mut MetricHistory = bytemuck::from_bytes_mut(header);
+    let samples: &mut [MetricSample] =
+        bytemuck::cast_slice_mut(&mut samples[..size_of::<MetricSample>() * header.capacity as usize]);
+    header.record(samples, timestamp, value);
+    Ok(())
+}
+
+fn split_metric_history(data: &[u8]) -> (&MetricHistory, &[MetricSample]) {
+    let (header, samples) = data[8..].split_at(size_of::<MetricHistory>());
+    let header: &MetricHistory = bytemuck::from_bytes(header);
+    let samples: &[MetricSample] =
+        bytemuck::cast_slice(&samples[..size_of::<MetricSample>() * header.capacity as usize]);
+    (header, samples)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ProposalAction {
+    ChangeOracle { oracle: Pubkey },
+    SetActive { active: bool },
+    SetPoolFee { fee_bps: u16 },
+    SetTradingMode { trading_mode: TradingMode },
+}
+
+#[account]
+pub struct Proposal {
+    pub bond: Pubkey,
+    pub proposer: Pubkey,
+    pub epoch: u64,
+    pub action: ProposalAction,
+    pub end_time: i64,
+    pub yes_votes: u64,
+    pub no_votes: u64,
+    pub quorum_votes: u64,
+    pub executed: bool,
+    pub bump: u8,
+}
+
+impl Proposal {
+    pub fn passed(&self) -> bool {
+        self.yes_votes > self.no_votes
+            && self.yes_votes.checked_add(self.no_votes).unwrap() >= self.quorum_votes
+    }
+
+    // A passed proposal left unexecuted past the execution period can no longer run
+    pub fn expired(&self, now: i64) -> bool {
+        now >= self.end_time.checked_add(GOVERNANCE_EXECUTION_PERIOD).unwrap()
+    }
+}
+
+#[account]
+pub struct VoteRecord {
+    pub proposal: Pubkey,
+    pub voter: Pubkey,
+    pub weight: u64,
+    pub support: bool,
+    pub bump: u8,
+}
+
+// Checked when a proposal opens, so an action that could never execute can't hold the slot
+fn validate_proposal_action(action: &ProposalAction, pool: Option<&Pool>) -> Result<()> {
+    match action {
+        ProposalAction::ChangeOracle { oracle } => {
+            require!(*oracle != Pubkey::default(), BondError::InvalidOracle);
+        }
+        ProposalAction::SetPoolFee { fee_bps } => {
+            require!(pool.is_some(), BondError::MissingPool);
+            require!(*fee_bps <= MAX_POOL_FEE_BPS, BondError::InvalidFee);
+        }
+        ProposalAction::SetActive { .. } | ProposalAction::SetTradingMode { .. } => {}
+    }
+    Ok(())
+}
+
+// Bond admin actions, shared by the creator's instructions and executed proposals
+fn apply_trading_mode(bond: &mut Bond, market: &Market, trading_mode: TradingMode) -> Result<()> {
+    require!(!market.batch_pending, BondError::BatchAuctionPending);
+    bond.trading_mode = trading_mode;
+
+    emit!(TradingModeChangedEvent {
+        bond_id: bond.id,
+        trading_mode,
+    });
+
+    Ok(())
+}
+
+fn apply_bond_oracle(bond: &mut Bond, oracle: Pubkey) {
+    bond.oracle = oracle;
+
+    emit!(BondOracleChangedEvent {
+        bond_id: bond.id,
+        oracle,
+    });
+}
+
+fn apply_bond_active(bond: &mut Bond, active: bool) {
+    bond.active = active;
+
+    emit!(BondActiveChangedEvent {
+        bond_id: bond.id,
+        active,
+    });
+}
+
+fn apply_pool_fee(bond: &Bond, pool: &mut Pool, fee_bps: u16) -> Result<()> {
+    require!(fee_bps <= MAX_POOL_FEE_BPS, BondError::InvalidFee);
+    pool.fee_bps = fee_bps;
+
+    emit!(PoolFeeChangedEvent {
+        bond_id: bond.id,
+        fee_bps,
+    });
+
+    Ok(())
+}
+
+// Common gate for every path that trades against a bond's price
+fn check_trading_allowed(bond: &Bond, market: &Market) -> Result<()> {
+    require!(bond.active, BondError::BondInactive);
+    check_not_halted(market)
+}
+
+// Gate for paths that settle or unwind at the bond's price, which stay open on inactive bonds
+fn check_not_halted(market: &Market) -> Result<()> {
+    require!(
+        !market.circuit_breaker.is_halted(Clock::get()?.slot),
+        BondError::TradingHalted
+    );
+    Ok(())
+}
+
+// Buybacks move supply like a trade, so they stop with the breaker and wait out a
+// pending batch or an open Dutch auction
+fn check_buyback_allowed(bond: &Bond, market: &Market) -> Result<()> {
+    check_trading_allowed(bond, market)?;
+    require!(!market.auction_active, BondError::DutchAuctionActive);
+    require!(!market.batch_pending, BondError::BatchAuctionPending);
+    Ok(())
+}
+
+#[account]
+pub struct InsuranceIncident {
+    pub bond: Pubkey,
+    pub incident_id: u64,
+    pub opened_at: i64,
+    pub total_compensation: u64,
+    pub total_claimed: u64,
+    pub bump: u8,
+}
 
-#[derive(Accounts)]
-pub struct UpdateMetric<'info> {
-    #[account(mut)]
-    pub bond: Account<'info, Bond>,
-    
-    #[account(mut)]
-    pub market: Account<'info, Market>,
-    
-    /// CHECK: Verified in instruction
-    pub oracle: Signer<'info>,
+#[account]
+pub struct InsuranceClaim {
+    pub incident: Pubkey,
+    pub holder: Pubkey,
+    pub mint: Pubkey, // Quote mint the compensation was reserved in
+    pub amount: u64,
+    pub claimed: bool,
+    pub bump: u8,
 }
 
-#[derive(Accounts)]
-pub struct TradeBond<'info> {
-    #[account(mut)]
-    pub bond: Account<'info, Bond>,
-    
-    #[account(mut)]
-    pub market: Account<'info, Market>,
-    
-    #[account(
-        mut,
-        seeds = [b"holder_bonds", bond.key().as_ref(), buyer.key().as_ref()],
-        bump
-    )]
-    pub holder_bonds: Account<'info, HolderBonds>,
-    
-    #[account(mut)]
-    pub vault: Account<'info, TokenAccount>,
-    
-    /// CHECK: PDA that controls the vault
-    #[account(
-        seeds = [b"vault_authority"],
-        bump
-    )]
-    pub vault_authority: AccountInfo<'info>,
-    
-    #[account(mut)]
-    pub buyer_token_account: Account<'info, TokenAccount>,
-    
-    #[account(mut)]
-    pub seller_token_account: Account<'info, TokenAccount>,
-    
-    pub buyer: Signer<'info>,
-    
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
+#[event]
+pub struct BondCreatedEvent {
+    pub bond_id: u64,
+    pub creator: Pubkey,
+    pub name: String,
+    pub total_supply: u64,
+    pub initial_price: u64,
+}
+
+#[event]
+pub struct BondMetadataUpdatedEvent {
+    pub bond_id: u64,
+    pub name: String,
+    pub metric: String,
+    pub metadata_uri: String,
+}
+
+#[event]
+pub struct MetricUpdatedEvent {
+    pub bond_id: u64,
+    pub old_value: u64,
+    pub new_value: u64,
+    pub new_price: u64,
+}
+
+#[event]
+pub struct BondTradedEvent {
+    pub bond_id: u64,
+    pub trader: Pubkey,
+    pub amount: u64,
+    pub price: u64,
+    pub is_buy: bool,
+}
+
+#[event]
+pub struct BondTransferredEvent {
+    pub bond_id: u64,
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct PoolCreatedEvent {
+    pub bond_id: u64,
+    pub pool: Pubkey,
+    pub quote_mint: Pubkey,
+    pub fee_bps: u16,
+}
+
+#[event]
+pub struct LiquidityChangedEvent {
+    pub bond_id: u64,
+    pub provider: Pubkey,
+    pub bond_amount: u64,
+    pub quote_amount: u64,
+    pub lp_amount: u64,
+    pub is_deposit: bool,
+}
+
+#[event]
+pub struct PoolSwapEvent {
+    pub bond_id: u64,
+    pub trader: Pubkey,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub is_buy: bool,
+    pub bond_reserve: u64,
+    pub quote_reserve: u64,
+}
+
+#[event]
+pub struct BondGraduatedEvent {
+    pub bond_id: u64,
+    pub pool: Pubkey,
+    pub market_cap: u64,
+    pub bond_amount: u64,
+    pub quote_amount: u64,
+}
+
+#[event]
+pub struct OrderPlacedEvent {
+    pub bond_id: u64,
+    pub order_id: u64,
+    pub owner: Pubkey,
+    pub is_bid: bool,
+    pub price: u64,
+    pub amount: u64,
+}
+
+#[event]
+pub struct OrderCancelledEvent {
+    pub bond_id: u64,
+    pub order_id: u64,
+    pub owner: Pubkey,
+    pub remaining_amount: u64,
+}
+
+#[event]
+pub struct OrderFilledEvent {
+    pub bond_id: u64,
+    pub bid_order_id: u64,
+    pub ask_order_id: u64,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub price: u64,
+    pub amount: u64,
+}
+
+#[event]
+pub struct TradingModeChangedEvent {
+    pub bond_id: u64,
+    pub trading_mode: TradingMode,
+}
+
+#[event]
+pub struct BatchOrderSubmittedEvent {
+    pub bond_id: u64,
+    pub epoch: u64,
+    pub order_index: u64,
+    pub owner: Pubkey,
+    pub is_buy: bool,
+    pub amount: u64,
+    pub limit_price: u64,
+}
+
+#[event]
+pub struct BatchAuctionClearedEvent {
+    pub bond_id: u64,
+    pub epoch: u64,
+    pub clearing_price: u64,
+    pub buy_fill_bps: u64,
+    pub sell_fill_bps: u64,
+    pub total_buy_filled: u64,
+    pub total_sell_filled: u64,
+}
+
+#[event]
+pub struct BatchOrderSettledEvent {
+    pub bond_id: u64,
+    pub epoch: u64,
+    pub order_index: u64,
+    pub owner: Pubkey,
+    pub is_buy: bool,
+    pub filled_amount: u64,
+    pub price: u64,
+}
+
+#[event]
+pub struct TradeCommittedEvent {
+    pub bond_id: u64,
+    pub trader: Pubkey,
+    pub commitment: [u8; 32],
+    pub commit_slot: u64,
+    pub deposit: u64,
+}
+
+#[event]
+pub struct TradeRevealedEvent {
+    pub bond_id: u64,
+    pub trader: Pubkey,
+    pub amount: u64,
+    pub is_buy: bool,
+    pub max_price: u64,
+}
+
+#[event]
+pub struct CommitmentForfeitedEvent {
+    pub bond_id: u64,
+    pub trader: Pubkey,
+    pub deposit: u64,
+}
+
+#[event]
+pub struct TradeLimitsUpdatedEvent {
+    pub bond_id: u64,
+    pub trade_limits: TradeLimits,
+}
+
+#[event]
+pub struct PresaleConfiguredEvent {
+    pub bond_id: u64,
+    pub merkle_root: [u8; 32],
+    pub public_sale_start: i64,
+}
+
+#[event]
+pub struct DutchAuctionStartedEvent {
+    pub bond_id: u64,
+    pub tranche_amount: u64,
+    pub start_price: u64,
+    pub floor_price: u64,
+    pub start_time: i64,
+    pub end_time: i64,
+}
+
+#[event]
+pub struct DutchAuctionBidEvent {
+    pub bond_id: u64,
+    pub bidder: Pubkey,
+    pub amount: u64,
+    pub price: u64,
+}
+
+#[event]
+pub struct DutchAuctionClearedEvent {
+    pub bond_id: u64,
+    pub clearing_price: u64,
+    pub sold_amount: u64,
+}
+
+#[event]
+pub struct DutchBidSettledEvent {
+    pub bond_id: u64,
+    pub bidder: Pubkey,
+    pub amount: u64,
+    pub refund_amount: u64,
+}
+
+#[event]
+pub struct ReferralRewardsClaimedEvent {
+    pub referrer: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct LoanUpdatedEvent {
+    pub bond_id: u64,
+    pub borrower: Pubkey,
+    pub collateral: u64,
+    pub debt: u64,
+}
+
+#[event]
+pub struct LoanLiquidatedEvent {
+    pub bond_id: u64,
+    pub borrower: Pubkey,
+    pub liquidator: Pubkey,
+    pub collateral: u64,
+    pub repaid: u64,
+    pub bad_debt: u64,
+}
+
+#[event]
+pub struct StakingRewardsFundedEvent {
+    pub bond_id: u64,
+    pub amount: u64,
+}
+
+#[event]
+pub struct StakeUpdatedEvent {
+    pub bond_id: u64,
+    pub staker: Pubkey,
+    pub staked: u64,
+    pub unstaking: u64,
+}
+
+#[event]
+pub struct StakingRewardsClaimedEvent {
+    pub bond_id: u64,
+    pub staker: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct PredictionMarketCreatedEvent {
+    pub bond_id: u64,
+    pub prediction_market: Pubkey,
+    pub threshold: u64,
+    pub resolve_time: i64,
+}
+
+#[event]
+pub struct PredictionMarketResolvedEvent {
+    pub bond_id: u64,
+    pub prediction_market: Pubkey,
+    pub metric_value: u64,
+    pub outcome_yes: bool,
+}
+
+#[event]
+pub struct PredictionPayoutClaimedEvent {
+    pub prediction_market: Pubkey,
+    pub user: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct PerpPositionOpenedEvent {
+    pub bond_id: u64,
+    pub trader: Pubkey,
+    pub is_long: bool,
+    pub size: u64,
+    pub entry_price: u64,
+    pub margin: u64,
+}
+
+#[event]
+pub struct PerpPositionClosedEvent {
+    pub bond_id: u64,
+    pub trader: Pubkey,
+    pub exit_price: u64,
+    pub realized_pnl: i64,
+    pub payout: u64,
+}
+
+#[event]
+pub struct PerpPositionLiquidatedEvent {
+    pub bond_id: u64,
+    pub trader: Pubkey,
+    pub liquidator: Pubkey,
+    pub size: u64,
+    pub mark_price: u64,
+    pub liquidation_fee: u64,
+}
+
+#[event]
+pub struct BasketRebalancedEvent {
+    pub basket: Pubkey,
+    pub constituents: Vec<BasketConstituent>,
+    pub value_in: u64,
+    pub value_out: u64,
+}
+
+#[event]
+pub struct BasketSharesMintedEvent {
+    pub basket: Pubkey,
+    pub user: Pubkey,
+    pub value: u64,
+    pub shares: u64,
+}
+
+#[event]
+pub struct BasketSharesRedeemedEvent {
+    pub basket: Pubkey,
+    pub user: Pubkey,
+    pub value: u64,
+    pub shares: u64,
+}
+
+#[event]
+pub struct BondOracleChangedEvent {
+    pub bond_id: u64,
+    pub oracle: Pubkey,
+}
+
+#[event]
+pub struct BondActiveChangedEvent {
+    pub bond_id: u64,
+    pub active: bool,
 }
 
-#[account]
-pub struct BondsState {
-    pub authority: Pubkey,
-    pub bond_counter: u64,
+#[event]
+pub struct PoolFeeChangedEvent {
+    pub bond_id: u64,
+    pub fee_bps: u16,
 }
 
-#[account]
-pub struct Bond {
-    pub id: u64,
-    pub creator: Pubkey,
-    pub name: String,
-    pub description: String,
-    pub category: String,
-    pub metric: String,
-    pub total_supply: u64,
-    pub current_supply: u64,
-    pub price: u64,
-    pub oracle: Pubkey,
-    pub current_value: u64,
-    pub last_update: i64,
-    pub active: bool,
+#[event]
+pub struct ProposalCreatedEvent {
+    pub bond_id: u64,
+    pub epoch: u64,
+    pub proposer: Pubkey,
+    pub action: ProposalAction,
+    pub end_time: i64,
 }
 
-#[account]
-pub struct Market {
-    pub bond: Pubkey,
-    pub total_volume: u64,
-    pub last_price: u64,
-    pub price_change_24h: i64,
-    pub market_cap: u64,
+#[event]
+pub struct VoteCastEvent {
+    pub bond_id: u64,
+    pub epoch: u64,
+    pub voter: Pubkey,
+    pub support: bool,
+    pub weight: u64,
 }
 
-#[account]
-pub struct CreatorBonds {
-    pub creator: Pubkey,
-    pub bond_ids: Vec<u64>,
+#[event]
+pub struct ProposalExecutedEvent {
+    pub bond_id: u64,
+    pub epoch: u64,
+    pub passed: bool,
+    pub yes_votes: u64,
+    pub no_votes: u64,
 }
 
-#[account]
-pub struct HolderBonds {
-    pub holder: Pubkey,
-    pub bond: Pubkey,
+#[event]
+pub struct ProposalClosedEvent {
+    pub bond_id: u64,
+    pub epoch: u64,
+    pub passed: bool,
+    pub executed: bool,
+}
+
+#[event]
+pub struct BondBuybackEvent {
+    pub bond_id: u64,
     pub amount: u64,
+    pub price: u64,
+    pub total_supply: u64,
 }
 
 #[event]
-pub struct BondCreatedEvent {
+pub struct OrderBookBuybackEvent {
     pub bond_id: u64,
-    pub creator: Pubkey,
-    pub name: String,
+    pub seller: Pubkey,
+    pub ask_order_id: u64,
+    pub amount: u64,
+    pub price: u64,
     pub total_supply: u64,
-    pub initial_price: u64,
 }
 
 #[event]
-pub struct MetricUpdatedEvent {
+pub struct TradingHaltedEvent {
     pub bond_id: u64,
-    pub old_value: u64,
-    pub new_value: u64,
-    pub new_price: u64,
+    pub slot: u64,
+    pub resume_slot: u64,
 }
 
 #[event]
-pub struct BondTradedEvent {
+pub struct TradingResumedEvent {
     pub bond_id: u64,
-    pub trader: Pubkey,
+}
+
+#[event]
+pub struct InsuranceIncidentOpenedEvent {
+    pub bond_id: u64,
+    pub incident_id: u64,
+}
+
+#[event]
+pub struct HolderCompensatedEvent {
+    pub bond_id: u64,
+    pub incident_id: u64,
+    pub holder: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct InsuranceClaimedEvent {
+    pub incident_id: u64,
+    pub holder: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct ProtocolFeesWithdrawnEvent {
+    pub mint: Pubkey,
+    pub destination: Pubkey,
     pub amount: u64,
-    pub price: u64,
-    pub is_buy: bool,
 }
 
 #[error_code]
@@ -401,4 +8360,1114 @@ pub enum BondError {
     InsufficientBonds,
     #[msg("Invalid oracle")]
     InvalidOracle,
+    #[msg("Market does not belong to bond")]
+    MarketBondMismatch,
+    #[msg("Unauthorized access")]
+    Unauthorized,
+    #[msg("Bond name is too long")]
+    NameTooLong,
+    #[msg("Bond description is too long")]
+    DescriptionTooLong,
+    #[msg("Bond metric is too long")]
+    MetricTooLong,
+    #[msg("Bond metadata URI is too long")]
+    MetadataUriTooLong,
+    #[msg("Invalid holder index page")]
+    InvalidHolderPage,
+    #[msg("Amount must be greater than zero")]
+    InvalidAmount,
+    #[msg("Cannot transfer bonds to yourself")]
+    SelfTransfer,
+    #[msg("Invalid fee")]
+    InvalidFee,
+    #[msg("Pool does not belong to bond")]
+    PoolBondMismatch,
+    #[msg("Invalid pool account")]
+    InvalidPoolAccount,
+    #[msg("Insufficient pool liquidity")]
+    InsufficientLiquidity,
+    #[msg("Slippage tolerance exceeded")]
+    SlippageExceeded,
+    #[msg("Bond has graduated to its pool")]
+    BondGraduated,
+    #[msg("Bond has not reached its graduation threshold")]
+    BondNotGraduated,
+    #[msg("Bond reserves have already been migrated")]
+    BondAlreadyMigrated,
+    #[msg("Invalid vault account")]
+    InvalidVault,
+    #[msg("Order book is full")]
+    OrderBookFull,
+    #[msg("Order not found")]
+    OrderNotFound,
+    #[msg("No crossing orders to match")]
+    NoCrossingOrders,
+    #[msg("Account does not match the order owner")]
+    InvalidOrderAccount,
+    #[msg("Order would trade against your own resting order")]
+    SelfTrade,
+    #[msg("Instruction is not available in the bond's trading mode")]
+    TradingModeMismatch,
+    #[msg("Batch auction account is required to close the epoch")]
+    MissingBatchAuction,
+    #[msg("Batch auction has open orders for the current epoch")]
+    BatchAuctionPending,
+    #[msg("Batch auction is full")]
+    BatchAuctionFull,
+    #[msg("Batch auction has not been cleared yet")]
+    BatchAuctionNotFinalized,
+    #[msg("Order has already been settled")]
+    OrderAlreadySettled,
+    #[msg("Commitment deposit is too small")]
+    InsufficientDeposit,
+    #[msg("Commitment cannot be revealed in the same slot")]
+    RevealTooEarly,
+    #[msg("Commitment reveal window has expired")]
+    RevealWindowExpired,
+    #[msg("Commitment reveal window is still open")]
+    RevealWindowOpen,
+    #[msg("Revealed trade does not match the commitment")]
+    CommitmentMismatch,
+    #[msg("Trade exceeds the per-transaction limit")]
+    TradeLimitExceeded,
+    #[msg("Trade exceeds the per-wallet holding limit")]
+    WalletLimitExceeded,
+    #[msg("Bond is in its allowlist presale")]
+    PresaleActive,
+    #[msg("Bond has no active presale")]
+    PresaleInactive,
+    #[msg("Public sale has already started")]
+    PublicSaleStarted,
+    #[msg("Public sale must start in the future")]
+    InvalidPresaleSchedule,
+    #[msg("Invalid merkle proof")]
+    InvalidMerkleProof,
+    #[msg("Purchase exceeds presale allocation")]
+    AllocationExceeded,
+    #[msg("Bond has already started trading")]
+    BondAlreadyTrading,
+    #[msg("Invalid auction schedule")]
+    InvalidAuctionSchedule,
+    #[msg("Bond is in its Dutch auction")]
+    DutchAuctionActive,
+    #[msg("Dutch auction is not open for bids")]
+    DutchAuctionNotOpen,
+    #[msg("Dutch auction has not ended")]
+    DutchAuctionNotEnded,
+    #[msg("Dutch auction has already cleared")]
+    DutchAuctionCleared,
+    #[msg("Dutch auction has not cleared yet")]
+    DutchAuctionNotCleared,
+    #[msg("Traders cannot refer themselves")]
+    SelfReferral,
+    #[msg("Nothing to claim")]
+    NothingToClaim,
+    #[msg("Invalid lending parameters")]
+    InvalidLendingParams,
+    #[msg("Loan would exceed the allowed loan-to-value")]
+    ExceedsLoanToValue,
+    #[msg("Loan is not eligible for liquidation")]
+    LoanHealthy,
+    #[msg("Borrowers cannot liquidate their own loan")]
+    SelfLiquidation,
+    #[msg("Invalid cooldown")]
+    InvalidCooldown,
+    #[msg("Unstake cooldown has not elapsed")]
+    CooldownActive,
+    #[msg("Resolve time must be in the future")]
+    InvalidResolveTime,
+    #[msg("Prediction market is closed to new shares")]
+    PredictionMarketClosed,
+    #[msg("Prediction market has already been resolved")]
+    PredictionMarketResolved,
+    #[msg("No metric reading is available for the prediction market's resolve time")]
+    PredictionMarketNotResolvable,
+    #[msg("Prediction market has not been resolved")]
+    PredictionMarketNotResolved,
+    #[msg("Invalid prediction share mint")]
+    InvalidPredictionMint,
+    #[msg("Invalid perp market parameters")]
+    InvalidPerpParams,
+    #[msg("Position exceeds the maximum leverage")]
+    ExceedsMaxLeverage,
+    #[msg("A perp position is already open")]
+    PerpPositionOpen,
+    #[msg("No open perp position")]
+    NoPerpPosition,
+    #[msg("Perp position is above maintenance margin")]
+    PerpPositionHealthy,
+    #[msg("Invalid basket constituents")]
+    InvalidBasketConstituents,
+    #[msg("Bond is not an active basket constituent")]
+    NotBasketConstituent,
+    #[msg("Missing or mismatched basket constituent accounts")]
+    InvalidBasketAccounts,
+    #[msg("Invalid basket share mint")]
+    InvalidBasketMint,
+    #[msg("Invalid metric components")]
+    InvalidMetricComponents,
+    #[msg("Invalid metric history capacity")]
+    InvalidHistoryCapacity,
+    #[msg("Invalid metric history interval")]
+    InvalidHistoryInterval,
+    #[msg("Bond already has an active proposal")]
+    ProposalActive,
+    #[msg("Voting period has ended")]
+    VotingClosed,
+    #[msg("Voting period is still open")]
+    VotingOpen,
+    #[msg("Proposal has already been executed")]
+    ProposalExecuted,
+    #[msg("Pool account is required for this action")]
+    MissingPool,
+    #[msg("Trading is halted by the circuit breaker")]
+    TradingHalted,
+    #[msg("Trading is not halted")]
+    TradingNotHalted,
+    #[msg("Invalid circuit breaker configuration")]
+    InvalidCircuitBreaker,
+    #[msg("Insurance fund cannot cover this compensation")]
+    InsufficientInsuranceFund,
+    #[msg("Creator bond index already uses the paged layout")]
+    CreatorBondsAlreadyMigrated,
+    #[msg("Holder page is full")]
+    HolderPageFull,
+    #[msg("A freed holder slot must be reused before opening a new holder page")]
+    HolderVacancyAvailable,
+    #[msg("Order is below the order book's minimum value")]
+    OrderTooSmall,
+    #[msg("Bonds state already uses the current layout")]
+    BondsStateAlreadyMigrated,
+    #[msg("Withdrawal exceeds accrued protocol fees")]
+    InsufficientProtocolFees,
+    #[msg("Token mint does not match the bond's quote mint")]
+    QuoteMintMismatch,
+    #[msg("Reward duration must be positive")]
+    InvalidRewardDuration,
+    #[msg("Prediction markets are not supported on composite bonds")]
+    CompositeBondUnsupported,
+    #[msg("Perp open interest exceeds the LP capital backing it")]
+    PerpOpenInterestCapExceeded,
+    #[msg("Perp market LP capital cannot cover this payout")]
+    PerpMarketInsolvent,
+    #[msg("Metric history account is required for this bond")]
+    MissingMetricHistory,
+    #[msg("Metric history does not reach back to that time")]
+    MetricHistoryUnavailable,
+    #[msg("Passed proposal was not executed in time")]
+    ProposalExpired,
+    #[msg("Passed proposal can still be executed")]
+    ProposalPending,
+    #[msg("No sell order at or below the bond price to buy back")]
+    NoBuybackOrders,
+    #[msg("Reserve cannot pay for the units sold into the batch")]
+    InsufficientReserve,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bond() -> Bond {
+        Bond {
+            id: 1,
+            creator: Pubkey::new_unique(),
+            name: "Followers".to_string(),
+            description: "Follower count bond".to_string(),
+            category: "social".to_string(),
+            metric: "followers".to_string(),
+            metadata_uri: "https://example.com/bond.json".to_string(),
+            total_supply: 1_000,
+            current_supply: 1_000,
+            price: 100,
+            oracle: Pubkey::new_unique(),
+            current_value: 0,
+            last_update: 0,
+            active: true,
+            trading_mode: TradingMode::Continuous,
+            trade_limits: TradeLimits::default(),
+            presale_merkle_root: [0; 32],
+            public_sale_start: 0,
+            bump: 255,
+        }
+    }
+
+    fn market(bond: Pubkey) -> Market {
+        Market {
+            bond,
+            total_volume: 0,
+            last_price: 0,
+            price_change_24h: 0,
+            market_cap: 0,
+            holder_count: 0,
+            holder_tail_page: 0,
+            holder_vacancies: 0,
+            quote_mint: Pubkey::default(),
+            reserve: 0,
+            graduated: false,
+            migrated: false,
+            batch_epoch: 0,
+            batch_pending: false,
+            auction_active: false,
+            has_metric_history: false,
+            governance_epoch: 0,
+            proposal_active: false,
+            circuit_breaker: CircuitBreaker::default(),
+            bump: 255,
+        }
+    }
+
+    fn holder_bonds(amount: u64) -> HolderBonds {
+        HolderBonds {
+            holder: Pubkey::new_unique(),
+            bond: Pubkey::new_unique(),
+            amount,
+            locked: 0,
+            holder_page: 0,
+            snapshot_epoch: 0,
+            snapshot_amount: 0,
+            bump: 255,
+        }
+    }
+
+    fn bonds_state() -> BondsState {
+        BondsState {
+            authority: Pubkey::new_unique(),
+            bond_counter: 0,
+            graduation_threshold: 50_000,
+            bump: 255,
+            trade_fee_bps: DEFAULT_TRADE_FEE_BPS,
+            referral_share_bps: DEFAULT_REFERRAL_SHARE_BPS,
+            insurance_share_bps: DEFAULT_INSURANCE_SHARE_BPS,
+        }
+    }
+
+    fn fee_ledger(mint: Pubkey) -> FeeLedger {
+        FeeLedger {
+            mint,
+            protocol_fees: 0,
+            insurance_fund: 0,
+            bump: 255,
+        }
+    }
+
+    fn pool(bond_reserve: u64, quote_reserve: u64, lp_supply: u64) -> Pool {
+        Pool {
+            bond: Pubkey::new_unique(),
+            quote_mint: Pubkey::new_unique(),
+            quote_vault: Pubkey::new_unique(),
+            lp_mint: Pubkey::new_unique(),
+            bond_reserve,
+            quote_reserve,
+            lp_supply,
+            fee_bps: DEFAULT_POOL_FEE_BPS,
+            bump: 255,
+        }
+    }
+
+    fn holder_page_data(bond: &Pubkey, holders: Vec<Pubkey>) -> Vec<u8> {
+        let page = HolderPage {
+            bond: *bond,
+            page_index: 0,
+            holders,
+        };
+        let mut data = Vec::new();
+        page.try_serialize(&mut data).unwrap();
+        data.resize(HolderPage::SPACE, 0);
+        data
+    }
+
+    fn holder_page_key(bond: &Pubkey, page_index: u64) -> Pubkey {
+        Pubkey::find_program_address(
+            &[b"holder_page", bond.as_ref(), page_index.to_le_bytes().as_ref()],
+            &crate::ID,
+        )
+        .0
+    }
+
+    // Bond metadata
+    #[test]
+    fn bond_space_matches_serialized_length() {
+        let bond = bond();
+        let mut data = Vec::new();
+        bond.try_serialize(&mut data).unwrap();
+        let space = Bond::space(
+            &bond.name,
+            &bond.description,
+            &bond.category,
+            &bond.metric,
+            &bond.metadata_uri,
+        );
+        assert_eq!(space, data.len());
+    }
+
+    #[test]
+    fn bond_metadata_rejects_long_name() {
+        assert!(validate_bond_metadata("name", "description", "metric", "uri").is_ok());
+        let name = "n".repeat(MAX_BOND_NAME_LEN + 1);
+        let res = validate_bond_metadata(&name, "description", "metric", "uri");
+        assert_eq!(res.unwrap_err(), BondError::NameTooLong.into());
+    }
+
+    // Holder index
+    #[test]
+    fn credit_and_debit_holder_update_page() {
+        let bond_key = Pubkey::new_unique();
+        let page_key = holder_page_key(&bond_key, 0);
+        let mut market = market(bond_key);
+        let mut holder_bonds = holder_bonds(0);
+        let mut lamports = 0;
+        let mut data = holder_page_data(&bond_key, Vec::new());
+        let page = AccountInfo::new(&page_key, false, true, &mut lamports, &mut data, &crate::ID, false, 0);
+
+        credit_holder(&mut market, &mut holder_bonds, &page, &page, &page, 10).unwrap();
+        assert_eq!(holder_bonds.amount, 10);
+        assert_eq!(market.holder_count, 1);
+        let stored = HolderPage::try_deserialize(&mut &page.try_borrow_data().unwrap()[..]).unwrap();
+        assert_eq!(stored.holders, vec![holder_bonds.holder]);
+
+        // The page sits before the tail, so emptying the position leaves a vacancy
+        market.holder_tail_page = 1;
+        debit_holder(&mut market, &mut holder_bonds, &page, 10).unwrap();
+        assert_eq!(market.holder_count, 0);
+        assert_eq!(market.holder_vacancies, 1);
+        let stored = HolderPage::try_deserialize(&mut &page.try_borrow_data().unwrap()[..]).unwrap();
+        assert!(stored.holders.is_empty());
+    }
+
+    #[test]
+    fn credit_holder_rejects_full_page() {
+        let bond_key = Pubkey::new_unique();
+        let page_key = holder_page_key(&bond_key, 0);
+        let mut market = market(bond_key);
+        let mut holder_bonds = holder_bonds(0);
+        let mut lamports = 0;
+        let holders = (0..HOLDER_PAGE_SIZE).map(|_| Pubkey::new_unique()).collect();
+        let mut data = holder_page_data(&bond_key, holders);
+        let page = AccountInfo::new(&page_key, false, true, &mut lamports, &mut data, &crate::ID, false, 0);
+
+        let res = credit_holder(&mut market, &mut holder_bonds, &page, &page, &page, 10);
+        assert_eq!(res.unwrap_err(), BondError::HolderPageFull.into());
+    }
+
+    #[test]
+    fn credit_holder_reuses_vacancy_before_new_page() {
+        let bond_key = Pubkey::new_unique();
+        let page_key = holder_page_key(&bond_key, 1);
+        let mut market = market(bond_key);
+        market.holder_tail_page = 1;
+        market.holder_vacancies = 1;
+        let mut holder_bonds = holder_bonds(0);
+        let mut lamports = 0;
+        let mut data = Vec::new();
+        let owner = Pubkey::default();
+        let page = AccountInfo::new(&page_key, false, true, &mut lamports, &mut data, &owner, false, 0);
+
+        let res = credit_holder(&mut market, &mut holder_bonds, &page, &page, &page, 10);
+        assert_eq!(res.unwrap_err(), BondError::HolderVacancyAvailable.into());
+    }
+
+    // Pools
+    #[test]
+    fn pool_prices_deposits_and_swaps() {
+        assert_eq!(pool(0, 0, 0).liquidity_for(400, 100), 200);
+        assert_eq!(pool(1_000, 500, 200).liquidity_for(100, 100), 20);
+        assert_eq!(pool(1_000, 1_000, 1_000).swap_output(100, true), 90);
+    }
+
+    #[test]
+    fn pool_rejects_empty_reserves_and_high_fee() {
+        assert_eq!(pool(0, 1_000, 1_000).swap_output(100, true), 0);
+        let res = apply_pool_fee(&bond(), &mut pool(1_000, 1_000, 1_000), MAX_POOL_FEE_BPS + 1);
+        assert_eq!(res.unwrap_err(), BondError::InvalidFee.into());
+    }
+
+    // Graduation
+    #[test]
+    fn market_graduates_at_threshold() {
+        let mut bond = bond();
+        bond.current_supply = 500;
+        let mut market = market(Pubkey::new_unique());
+        market.refresh_market_cap(&bond);
+        assert_eq!(market.market_cap, 50_000);
+        assert!(market.has_graduated(50_000));
+        assert!(!market.has_graduated(50_001));
+        assert!(!market.has_graduated(0));
+    }
+
+    #[test]
+    fn graduation_deposit_prices_empty_pool_at_bond_price() {
+        // The reserve buys 50 of the 80 unsold units at the bond price
+        assert_eq!(pool(0, 0, 0).graduation_deposit(100, 5_000, 80), (50, 5_000));
+        // A reserve worth more than the unsold units still goes in whole
+        assert_eq!(pool(0, 0, 0).graduation_deposit(100, 5_000, 20), (20, 5_000));
+    }
+
+    #[test]
+    fn graduation_deposit_keeps_seeded_pool_ratio() {
+        // The pool trades at 10 quote per unit, not the bond price of 100
+        let seeded = pool(100, 1_000, 100);
+        assert_eq!(seeded.graduation_deposit(100, 5_000, 800), (500, 5_000));
+        // Too few unsold units leaves part of the reserve unpaired
+        assert_eq!(seeded.graduation_deposit(100, 5_000, 200), (200, 2_000));
+    }
+
+    // Order book
+    #[test]
+    fn order_book_matches_by_price_time_and_closes_dust() {
+        let mut book: OrderBook = bytemuck::Zeroable::zeroed();
+        book.min_order_value = 1_000;
+        let owner = Pubkey::new_unique();
+        book.insert(owner, false, 110, 50).unwrap();
+        let first = book.insert(owner, false, 100, 50).unwrap();
+        book.insert(owner, false, 100, 50).unwrap();
+
+        let best = book.best_ask().unwrap();
+        assert_eq!(book.orders[best].order_id, first);
+        assert!(book.crosses_own_order(&owner, true, 100));
+        assert!(!book.crosses_own_order(&owner, true, 99));
+
+        // 5 units left at 100 are worth less than the minimum, so the order closes
+        assert_eq!(book.fill(best, 45), 5);
+        assert!(book.find(first).is_none());
+        assert_eq!(book.order_count, 2);
+    }
+
+    #[test]
+    fn order_book_rejects_insert_when_full() {
+        let mut book: OrderBook = bytemuck::Zeroable::zeroed();
+        for _ in 0..MAX_ORDERS {
+            book.insert(Pubkey::new_unique(), true, 100, 1).unwrap();
+        }
+        let res = book.insert(Pubkey::new_unique(), true, 100, 1);
+        assert_eq!(res.unwrap_err(), BondError::OrderBookFull.into());
+    }
+
+    // Batch auctions
+    #[test]
+    fn batch_auction_rations_oversubscribed_buys() {
+        let mut bond = bond();
+        bond.current_supply = 100;
+        let mut market = market(Pubkey::new_unique());
+        let order = |is_buy, amount, limit_price| BatchOrder {
+            owner: Pubkey::new_unique(),
+            is_buy,
+            amount,
+            limit_price,
+            settled: false,
+        };
+        let mut auction = BatchAuction {
+            bond: Pubkey::new_unique(),
+            epoch: 0,
+            clearing_price: 0,
+            buy_fill_bps: 0,
+            sell_fill_bps: 0,
+            finalized: false,
+            bump: 255,
+            orders: vec![order(true, 150, 100), order(true, 50, 120), order(true, 80, 90)],
+        };
+
+        assert_eq!(auction.finalize(&mut bond, &mut market).unwrap(), (100, 0));
+        assert_eq!(auction.buy_fill_bps, 5_000);
+        assert_eq!(bond.current_supply, 0);
+        assert_eq!(market.reserve, 10_000);
+        assert_eq!(BatchAuction::filled_amount(&auction.orders[0], 100, 5_000, 10_000), 75);
+    }
+
+    #[test]
+    fn batch_auction_rations_sells_against_reserve() {
+        let mut bond = bond();
+        bond.current_supply = 0;
+        let mut market = market(Pubkey::new_unique());
+        market.reserve = 1_000;
+        let order = |is_buy, amount| BatchOrder {
+            owner: Pubkey::new_unique(),
+            is_buy,
+            amount,
+            limit_price: 100,
+            settled: false,
+        };
+        let mut auction = BatchAuction {
+            bond: Pubkey::new_unique(),
+            epoch: 0,
+            clearing_price: 0,
+            buy_fill_bps: 0,
+            sell_fill_bps: 0,
+            finalized: false,
+            bump: 255,
+            orders: vec![order(true, 10), order(false, 30)],
+        };
+
+        // The reserve pays for 10 units and the buyers for another 10
+        assert_eq!(auction.finalize(&mut bond, &mut market).unwrap(), (10, 19));
+        assert_eq!(auction.sell_fill_bps, 6_666);
+        assert_eq!(bond.current_supply, 9);
+        assert_eq!(market.reserve, 100);
+        assert_eq!(BatchAuction::filled_amount(&auction.orders[1], 100, 10_000, 6_666), 19);
+    }
+
+    #[test]
+    fn batch_auction_skips_orders_outside_clearing_price() {
+        let order = |is_buy, limit_price| BatchOrder {
+            owner: Pubkey::new_unique(),
+            is_buy,
+            amount: 10,
+            limit_price,
+            settled: false,
+        };
+        assert_eq!(BatchAuction::filled_amount(&order(true, 99), 100, 10_000, 10_000), 0);
+        assert_eq!(BatchAuction::filled_amount(&order(false, 101), 100, 10_000, 10_000), 0);
+    }
+
+    // Trade limits
+    #[test]
+    fn trade_limits_tighten_during_launch() {
+        let limits = TradeLimits {
+            max_wallet_amount: 1_000,
+            max_trade_amount: 0,
+            launch_window_end: 100,
+            launch_max_wallet_amount: 0,
+            launch_max_trade_amount: 50,
+        };
+        assert_eq!(limits.current_caps(99), (1_000, 50));
+        assert_eq!(limits.current_caps(100), (1_000, 0));
+        assert!(limits.check_buy(99, 0, 50).is_ok());
+        assert!(limits.check_buy(100, 0, 500).is_ok());
+    }
+
+    #[test]
+    fn trade_limits_reject_whale_buys() {
+        let limits = TradeLimits {
+            max_wallet_amount: 1_000,
+            max_trade_amount: 0,
+            launch_window_end: 100,
+            launch_max_wallet_amount: 0,
+            launch_max_trade_amount: 50,
+        };
+        let res = limits.check_buy(99, 0, 51);
+        assert_eq!(res.unwrap_err(), BondError::TradeLimitExceeded.into());
+        let res = limits.check_buy(100, 990, 11);
+        assert_eq!(res.unwrap_err(), BondError::WalletLimitExceeded.into());
+    }
+
+    #[test]
+    fn trade_limits_bound_units_credited_on_fill() {
+        let limits = TradeLimits {
+            max_wallet_amount: 1_000,
+            max_trade_amount: 0,
+            launch_window_end: 100,
+            launch_max_wallet_amount: 500,
+            launch_max_trade_amount: 0,
+        };
+        assert_eq!(limits.holding_room(99, 480), 20);
+        assert_eq!(limits.holding_room(100, 480), 520);
+        assert_eq!(limits.holding_room(100, 1_200), 0);
+        assert_eq!(TradeLimits::default().holding_room(0, 1_200), u64::MAX);
+    }
+
+    // Presale
+    #[test]
+    fn merkle_proof_verifies_allowlisted_leaf() {
+        let leaves: Vec<[u8; 32]> = (0u8..4).map(|i| hashv(&[&[i]]).to_bytes()).collect();
+        let pair = |a: [u8; 32], b: [u8; 32]| {
+            if a <= b {
+                hashv(&[&a, &b]).to_bytes()
+            } else {
+                hashv(&[&b, &a]).to_bytes()
+            }
+        };
+        let left = pair(leaves[0], leaves[1]);
+        let right = pair(leaves[2], leaves[3]);
+        let root = pair(left, right);
+
+        assert!(verify_merkle_proof(&[leaves[3], left], root, leaves[2]));
+        assert!(!verify_merkle_proof(&[leaves[3], left], root, leaves[0]));
+
+        let mut bond = bond();
+        bond.presale_merkle_root = root;
+        bond.public_sale_start = 100;
+        assert!(bond.presale_active(99));
+        assert!(!bond.presale_active(100));
+    }
+
+    // Dutch auctions
+    fn dutch_auction(sold_amount: u64) -> DutchAuction {
+        DutchAuction {
+            bond: Pubkey::new_unique(),
+            tranche_amount: 100,
+            start_price: 1_000,
+            floor_price: 200,
+            start_time: 0,
+            end_time: 100,
+            sold_amount,
+            last_bid_price: 0,
+            clearing_price: 0,
+            cleared: false,
+            bump: 255,
+        }
+    }
+
+    #[test]
+    fn dutch_auction_decays_to_floor() {
+        let auction = dutch_auction(0);
+        assert_eq!(auction.current_price(0), 1_000);
+        assert_eq!(auction.current_price(25), 800);
+        assert_eq!(auction.current_price(100), 200);
+        assert_eq!(auction.current_price(200), 200);
+    }
+
+    #[test]
+    fn dutch_auction_rejects_oversold_tranche() {
+        let mut bond = bond();
+        let mut market = market(Pubkey::new_unique());
+        let res = clear_dutch_auction(&mut dutch_auction(101), &mut bond, &mut market, 500);
+        assert_eq!(res.unwrap_err(), BondError::InvalidSupply.into());
+    }
+
+    // Trade fees
+    #[test]
+    fn trade_fee_splits_between_referrer_insurance_and_protocol() {
+        let bonds_state = bonds_state();
+        let mut ledger = fee_ledger(Pubkey::new_unique());
+        let mut referrer_stats = ReferrerStats {
+            referrer: Pubkey::new_unique(),
+            claimable: 0,
+            lifetime_volume: 0,
+            lifetime_rewards: 0,
+            bump: 255,
+        };
+
+        let fee = bonds_state.trade_fee(100_000);
+        assert_eq!(fee, 1_000);
+        distribute_trade_fee(&bonds_state, &mut ledger, Some(&mut referrer_stats), 100_000, fee);
+        assert_eq!(referrer_stats.claimable, 200);
+        assert_eq!(referrer_stats.lifetime_volume, 100_000);
+        assert_eq!(ledger.insurance_fund, 160);
+        assert_eq!(ledger.protocol_fees, 640);
+    }
+
+    #[test]
+    fn trade_fee_without_referrer_stays_in_its_mint_ledger() {
+        let bonds_state = bonds_state();
+        let mut usdc = fee_ledger(Pubkey::new_unique());
+        let mut other = fee_ledger(Pubkey::new_unique());
+
+        distribute_trade_fee(&bonds_state, &mut usdc, None, 100_000, 1_000);
+        assert_eq!(usdc.insurance_fund, 200);
+        assert_eq!(usdc.protocol_fees, 800);
+        assert_eq!(other.insurance_fund, 0);
+
+        distribute_trade_fee(&bonds_state, &mut other, None, 10_000, 100);
+        assert_eq!(other.insurance_fund, 20);
+        assert_eq!(usdc.insurance_fund, 200);
+    }
+
+    #[test]
+    fn settlement_fee_comes_out_of_the_escrow() {
+        let bonds_state = bonds_state();
+        let mut ledger = fee_ledger(Pubkey::new_unique());
+        assert_eq!(batch_escrow(&bonds_state, 10, 100), 1_010);
+
+        assert_eq!(charge_settlement_fee(&bonds_state, &mut ledger, 1_000, 10), 10);
+        assert_eq!(ledger.insurance_fund, 2);
+        assert_eq!(ledger.protocol_fees, 8);
+
+        // A rate raised after the order was placed can't take more than was escrowed
+        assert_eq!(charge_settlement_fee(&bonds_state, &mut ledger, 1_000, 5), 5);
+        assert_eq!(ledger.insurance_fund + ledger.protocol_fees, 15);
+    }
+
+    // Lending
+    fn lending_pool() -> LendingPool {
+        LendingPool {
+            bond: Pubkey::new_unique(),
+            quote_mint: Pubkey::new_unique(),
+            quote_vault: Pubkey::new_unique(),
+            total_deposits: 10_000,
+            total_shares: 10_000,
+            total_borrowed: 5_000,
+            accrued_interest: 0,
+            bad_debt: 0,
+            last_accrual: 1,
+            ltv_bps: 5_000,
+            liquidation_threshold_bps: 8_000,
+            interest_rate_bps: 1_000,
+            bump: 255,
+        }
+    }
+
+    fn loan() -> Loan {
+        Loan {
+            borrower: Pubkey::new_unique(),
+            bond: Pubkey::new_unique(),
+            collateral: 100,
+            principal: 5_000,
+            interest: 0,
+            last_accrual: 1,
+            bump: 255,
+        }
+    }
+
+    #[test]
+    fn lending_accrues_interest_to_lenders_and_repays_interest_first() {
+        let mut pool = lending_pool();
+        let mut loan = loan();
+        let now = 1 + SECONDS_PER_YEAR as i64;
+        pool.accrue(now);
+        loan.accrue(pool.interest_rate_bps, now);
+        assert_eq!(pool.accrued_interest, 500);
+        assert_eq!(pool.value_of(10_000), 10_500);
+        assert_eq!(loan.debt(), 5_500);
+
+        let (interest_paid, principal_paid) = loan.apply_repayment(1_500);
+        assert_eq!((interest_paid, principal_paid), (500, 1_000));
+        pool.book_repayment(interest_paid, principal_paid);
+        assert_eq!(pool.accrued_interest, 0);
+        assert_eq!(pool.total_borrowed, 4_000);
+        assert_eq!(loan.borrow_limit(100, pool.ltv_bps), 5_000);
+    }
+
+    #[test]
+    fn lending_writes_off_bad_debt_and_caps_seized_collateral() {
+        let mut pool = lending_pool();
+        pool.write_off(0, 5_000);
+        assert_eq!(pool.total_deposits, 5_000);
+        assert_eq!(pool.bad_debt, 5_000);
+        assert_eq!(pool.value_of(10_000), 5_000);
+
+        let loan = loan();
+        assert_eq!(loan.seizable_collateral(1_000, 100), 10);
+        assert_eq!(loan.seizable_collateral(1_000_000, 100), 100);
+        assert_eq!(loan.seizable_collateral(1_000, 0), 100);
+    }
+
+    // Staking
+    fn staking_pool(total_staked: u64) -> StakingPool {
+        StakingPool {
+            bond: Pubkey::new_unique(),
+            reward_mint: Pubkey::new_unique(),
+            reward_vault: Pubkey::new_unique(),
+            total_staked,
+            acc_reward_per_share: 0,
+            reward_rate: 0,
+            reward_duration: 100,
+            reward_end: 0,
+            last_update: 0,
+            unstake_cooldown: 0,
+            bump: 255,
+        }
+    }
+
+    #[test]
+    fn staking_streams_rewards_to_stakers() {
+        let mut pool = staking_pool(10);
+        pool.fund(1_000, 0);
+        pool.update_rewards(50);
+
+        let mut position = StakePosition {
+            staker: Pubkey::new_unique(),
+            staking_pool: Pubkey::new_unique(),
+            amount: 10,
+            reward_debt: 0,
+            pending_rewards: 0,
+            unstaking_amount: 0,
+            unstake_available_at: 0,
+            bump: 255,
+        };
+        position.harvest(pool.acc_reward_per_share);
+        assert_eq!(position.pending_rewards, 500);
+
+        position.sync(pool.acc_reward_per_share);
+        pool.update_rewards(500);
+        position.harvest(pool.acc_reward_per_share);
+        assert_eq!(position.pending_rewards, 1_000);
+    }
+
+    #[test]
+    fn staking_pauses_stream_without_stakers() {
+        let mut pool = staking_pool(0);
+        pool.fund(1_000, 0);
+        pool.update_rewards(40);
+        assert_eq!(pool.acc_reward_per_share, 0);
+        assert_eq!(pool.reward_end, 140);
+    }
+
+    // Prediction markets
+    #[test]
+    fn prediction_market_keeps_value_in_effect_at_resolve_time() {
+        let mut prediction_market = PredictionMarket {
+            bond: Pubkey::new_unique(),
+            creator: Pubkey::new_unique(),
+            collateral_mint: Pubkey::new_unique(),
+            collateral_vault: Pubkey::new_unique(),
+            yes_mint: Pubkey::new_unique(),
+            no_mint: Pubkey::new_unique(),
+            threshold: 1_000,
+            resolve_time: 100,
+            outstanding_sets: 0,
+            resolution_value: 0,
+            value_recorded: false,
+            resolved: false,
+            outcome_yes: false,
+            bump: 255,
+        };
+        assert!(!prediction_market.record_reading(5_000, 100));
+        assert!(prediction_market.record_reading(800, 101));
+        assert!(!prediction_market.record_reading(5_000, 102));
+        assert_eq!(prediction_market.resolution_value, 800);
+    }
+
+    // Perps
+    fn perp_market(lp_capital: u64) -> PerpMarket {
+        PerpMarket {
+            bond: Pubkey::new_unique(),
+            collateral_mint: Pubkey::new_unique(),
+            collateral_vault: Pubkey::new_unique(),
+            max_leverage: 10,
+            maintenance_margin_bps: 500,
+            funding_rate_bps: 10,
+            long_open_interest: 0,
+            short_open_interest: 0,
+            cumulative_funding: 0,
+            last_funding_time: 0,
+            lp_capital,
+            lp_shares: lp_capital,
+            total_margin: 1_000,
+            realized_pnl: 0,
+            bad_debt: 0,
+            bump: 255,
+        }
+    }
+
+    #[test]
+    fn perp_settles_profit_from_lp_capital() {
+        let mut market = perp_market(5_000);
+        market.add_open_interest(true, 30);
+        market.add_open_interest(false, 10);
+        assert_eq!(market.net_exposure(100), 2_000);
+
+        let position = PerpPosition {
+            trader: Pubkey::new_unique(),
+            perp_market: Pubkey::new_unique(),
+            is_long: true,
+            size: 10,
+            entry_price: 100,
+            margin: 1_000,
+            funding_snapshot: 0,
+            bump: 255,
+        };
+        let equity = position.equity(150, 0);
+        assert_eq!(equity, 1_500);
+        market.settle_position(position.margin, equity, 1_500).unwrap();
+        assert_eq!(market.lp_capital, 4_500);
+        assert_eq!(market.realized_pnl, 500);
+        assert_eq!(market.value_of(5_000), 4_500);
+    }
+
+    #[test]
+    fn perp_rejects_payout_beyond_lp_capital_and_books_bad_debt() {
+        let mut market = perp_market(100);
+        let res = market.settle_position(1_000, 1_500, 1_500);
+        assert_eq!(res.unwrap_err(), BondError::PerpMarketInsolvent.into());
+
+        let mut market = perp_market(100);
+        market.settle_position(1_000, -200, 0).unwrap();
+        assert_eq!(market.lp_capital, 1_100);
+        assert_eq!(market.bad_debt, 200);
+    }
+
+    // Baskets
+    #[test]
+    fn basket_constituents_sum_to_full_weight() {
+        let a = Pubkey::new_unique();
+        let b = Pubkey::new_unique();
+        let previous = [BasketConstituent { bond: a, weight_bps: 10_000 }];
+        let constituents = [
+            BasketConstituent { bond: a, weight_bps: 0 },
+            BasketConstituent { bond: b, weight_bps: 10_000 },
+        ];
+        assert!(validate_basket_constituents(&constituents, &previous).is_ok());
+    }
+
+    #[test]
+    fn basket_constituents_reject_bad_weights_duplicates_and_drops() {
+        let a = Pubkey::new_unique();
+        let b = Pubkey::new_unique();
+        let previous = [BasketConstituent { bond: a, weight_bps: 10_000 }];
+
+        let short = [BasketConstituent { bond: a, weight_bps: 9_999 }];
+        let res = validate_basket_constituents(&short, &[]);
+        assert_eq!(res.unwrap_err(), BondError::InvalidBasketConstituents.into());
+
+        let duplicate = [
+            BasketConstituent { bond: a, weight_bps: 5_000 },
+            BasketConstituent { bond: a, weight_bps: 5_000 },
+        ];
+        let res = validate_basket_constituents(&duplicate, &[]);
+        assert_eq!(res.unwrap_err(), BondError::InvalidBasketConstituents.into());
+
+        let dropped = [BasketConstituent { bond: b, weight_bps: 10_000 }];
+        let res = validate_basket_constituents(&dropped, &previous);
+        assert_eq!(res.unwrap_err(), BondError::InvalidBasketConstituents.into());
+    }
+
+    // Composite metrics
+    fn composite_metric(oracles: &[Pubkey]) -> CompositeMetric {
+        CompositeMetric {
+            bond: Pubkey::new_unique(),
+            components: oracles
+                .iter()
+                .map(|oracle| MetricComponent {
+                    name: "component".to_string(),
+                    oracle: *oracle,
+                    weight_bps: (10_000 / oracles.len()) as u16,
+                    base_value: 0,
+                    current_value: 0,
+                    last_update: 0,
+                })
+                .collect(),
+            bump: 255,
+        }
+    }
+
+    #[test]
+    fn composite_index_tracks_components_against_baseline() {
+        let a = Pubkey::new_unique();
+        let b = Pubkey::new_unique();
+        let mut metric = composite_metric(&[a, b]);
+        assert_eq!(metric.index(), COMPOSITE_INDEX_SCALE);
+        assert_eq!(metric.record(&a, 200, 1).unwrap(), COMPOSITE_INDEX_SCALE);
+        assert_eq!(metric.record(&a, 300, 2).unwrap(), 1_250_000);
+    }
+
+    #[test]
+    fn composite_index_rejects_unknown_oracle() {
+        let mut metric = composite_metric(&[Pubkey::new_unique()]);
+        let res = metric.record(&Pubkey::new_unique(), 100, 1);
+        assert_eq!(res.unwrap_err(), BondError::InvalidOracle.into());
+    }
+
+    // Metric history
+    fn metric_history(capacity: u32, min_interval: i64) -> MetricHistory {
+        let mut history: MetricHistory = bytemuck::Zeroable::zeroed();
+        history.capacity = capacity;
+        history.min_interval = min_interval;
+        history
+    }
+
+    #[test]
+    fn metric_history_downsamples_and_wraps() {
+        let mut history = metric_history(3, 10);
+        let mut samples = [MetricSample { timestamp: 0, value: 0 }; 3];
+        history.record(&mut samples, 100, 1);
+        history.record(&mut samples, 105, 2);
+        assert_eq!(history.len, 1);
+        assert_eq!(samples[0].timestamp, 105);
+        assert_eq!(samples[0].value, 2);
+
+        for (timestamp, value) in [(110, 3), (120, 4), (130, 5)] {
+            history.record(&mut samples, timestamp, value);
+        }
+        let values: Vec<u64> = history.chronological(&samples).map(|sample| sample.value).collect();
+        assert_eq!(values, vec![3, 4, 5]);
+        assert_eq!(history.value_at(&samples, 125), Some(4));
+    }
+
+    #[test]
+    fn metric_history_has_no_value_before_first_sample() {
+        let mut history = metric_history(3, 10);
+        let mut samples = [MetricSample { timestamp: 0, value: 0 }; 3];
+        assert_eq!(history.value_at(&samples, 100), None);
+        history.record(&mut samples, 100, 1);
+        assert_eq!(history.value_at(&samples, 99), None);
+    }
+
+    // Governance
+    fn proposal(yes_votes: u64, no_votes: u64) -> Proposal {
+        Proposal {
+            bond: Pubkey::new_unique(),
+            proposer: Pubkey::new_unique(),
+            epoch: 1,
+            action: ProposalAction::SetActive { active: false },
+            end_time: 1_000,
+            yes_votes,
+            no_votes,
+            quorum_votes: 100,
+            executed: false,
+            bump: 255,
+        }
+    }
+
+    #[test]
+    fn proposal_passes_with_quorum_until_expiry() {
+        let proposal = proposal(80, 30);
+        assert!(proposal.passed());
+        assert!(!proposal.expired(1_000 + GOVERNANCE_EXECUTION_PERIOD - 1));
+        assert!(proposal.expired(1_000 + GOVERNANCE_EXECUTION_PERIOD));
+        assert!(!self::proposal(60, 30).passed());
+        assert!(!self::proposal(50, 50).passed());
+
+        let pool = pool(1_000, 1_000, 1_000);
+        let action = ProposalAction::SetPoolFee { fee_bps: MAX_POOL_FEE_BPS };
+        assert!(validate_proposal_action(&action, Some(&pool)).is_ok());
+    }
+
+    #[test]
+    fn proposal_rejects_actions_that_cannot_execute() {
+        let action = ProposalAction::SetPoolFee { fee_bps: 50 };
+        let res = validate_proposal_action(&action, None);
+        assert_eq!(res.unwrap_err(), BondError::MissingPool.into());
+
+        let pool = pool(1_000, 1_000, 1_000);
+        let action = ProposalAction::SetPoolFee { fee_bps: MAX_POOL_FEE_BPS + 1 };
+        let res = validate_proposal_action(&action, Some(&pool));
+        assert_eq!(res.unwrap_err(), BondError::InvalidFee.into());
+
+        let action = ProposalAction::ChangeOracle { oracle: Pubkey::default() };
+        let res = validate_proposal_action(&action, None);
+        assert_eq!(res.unwrap_err(), BondError::InvalidOracle.into());
+    }
+
+    #[test]
+    fn voting_weight_uses_balance_at_epoch_start() {
+        let mut holder_bonds = holder_bonds(100);
+        assert_eq!(holder_bonds.voting_weight(1), 100);
+        holder_bonds.snapshot(1);
+        holder_bonds.amount = 1_000;
+        holder_bonds.snapshot(1);
+        assert_eq!(holder_bonds.voting_weight(1), 100);
+        assert_eq!(holder_bonds.voting_weight(2), 1_000);
+    }
+
+    // Circuit breaker
+    fn circuit_breaker() -> CircuitBreaker {
+        CircuitBreaker {
+            window_slots: 80,
+            threshold_bps: 1_000,
+            cooldown_slots: 50,
+            ..CircuitBreaker::default()
+        }
+    }
+
+    #[test]
+    fn circuit_breaker_trips_on_moves_across_buckets() {
+        let mut breaker = circuit_breaker();
+        assert!(!breaker.record(100, 105, 0));
+        assert!(!breaker.record(105, 100, 30));
+        assert_eq!(breaker.window_move_bps(30), 976);
+        assert!(breaker.record(100, 101, 60));
+        assert!(breaker.is_halted(60));
+        assert!(breaker.is_halted(109));
+        assert!(!breaker.is_halted(110));
+    }
+
+    #[test]
+    fn circuit_breaker_ignores_moves_rolled_out_of_window() {
+        let mut breaker = circuit_breaker();
+        assert!(!breaker.record(100, 105, 0));
+        assert!(!breaker.record(105, 100, 30));
+        assert!(!breaker.record(100, 101, 90));
+        assert_eq!(breaker.window_move_bps(90), 576);
+        assert!(!breaker.is_halted(90));
+    }
 }
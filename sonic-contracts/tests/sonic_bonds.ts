@@ -0,0 +1,515 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program, BN } from "@coral-xyz/anchor";
+import {
+  Keypair,
+  LAMPORTS_PER_SOL,
+  PublicKey,
+  SystemProgram,
+} from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  createAccount,
+  createMint,
+  getAccount,
+  mintTo,
+} from "@solana/spl-token";
+import { createHash } from "crypto";
+import { expect } from "chai";
+import { SonicBonds } from "../target/types/sonic_bonds";
+
+const BOND_PRICE = 1_000;
+const MIN_COMMIT_DEPOSIT = 10_000_000;
+const CREATOR_BONDS_PAGE_SIZE = 50;
+
+describe("sonic_bonds", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.SonicBonds as Program<SonicBonds>;
+  const connection = provider.connection;
+  const authority = (provider.wallet as anchor.Wallet).payer;
+
+  const creator = Keypair.generate();
+  const trader = Keypair.generate();
+  const recipient = Keypair.generate();
+  const oracle = Keypair.generate();
+
+  let quoteMint: PublicKey;
+  let otherMint: PublicKey;
+  let vault: PublicKey;
+  let otherVault: PublicKey;
+  let traderQuote: PublicKey;
+  let traderOther: PublicKey;
+  let creatorQuote: PublicKey;
+
+  const pda = (...seeds: (Buffer | Uint8Array)[]) =>
+    PublicKey.findProgramAddressSync(seeds, program.programId)[0];
+  const u64 = (value: number | BN) =>
+    new BN(value).toArrayLike(Buffer, "le", 8);
+
+  const bondsStatePda = pda(Buffer.from("bonds_state"));
+  const vaultAuthorityPda = pda(Buffer.from("vault_authority"));
+  const bondPda = (bondId: BN) => pda(Buffer.from("bond"), u64(bondId));
+  const marketPda = (bond: PublicKey) =>
+    pda(Buffer.from("market"), bond.toBuffer());
+  const holderBondsPda = (bond: PublicKey, holder: PublicKey) =>
+    pda(Buffer.from("holder_bonds"), bond.toBuffer(), holder.toBuffer());
+  const holderPagePda = (bond: PublicKey, pageIndex: number | BN) =>
+    pda(Buffer.from("holder_page"), bond.toBuffer(), u64(pageIndex));
+  const creatorBondsPda = (owner: PublicKey) =>
+    pda(Buffer.from("creator_bonds"), owner.toBuffer());
+  const creatorBondsPagePda = (owner: PublicKey, pageIndex: number) =>
+    pda(Buffer.from("creator_bonds_page"), owner.toBuffer(), u64(pageIndex));
+  const feeLedgerPda = (mint: PublicKey) =>
+    pda(Buffer.from("fee_ledger"), mint.toBuffer());
+
+  async function expectError(promise: Promise<unknown>, code: string) {
+    try {
+      await promise;
+    } catch (err) {
+      expect(err.error?.errorCode?.code).to.equal(code);
+      return;
+    }
+    expect.fail(`expected ${code}`);
+  }
+
+  async function airdrop(to: PublicKey) {
+    const signature = await connection.requestAirdrop(to, 10 * LAMPORTS_PER_SOL);
+    await connection.confirmTransaction(signature, "confirmed");
+  }
+
+  async function createBond(owner: Keypair, category = "social") {
+    const bondsState = await program.account.bondsState.fetch(bondsStatePda);
+    const bondId = bondsState.bondCounter.addn(1);
+    const creatorBonds = await program.account.creatorBonds.fetchNullable(
+      creatorBondsPda(owner.publicKey)
+    );
+    const pageIndex = creatorBonds
+      ? Math.floor(creatorBonds.bondCount.toNumber() / CREATOR_BONDS_PAGE_SIZE)
+      : 0;
+    const bond = bondPda(bondId);
+
+    await program.methods
+      .createBond(
+        "Followers",
+        "Follower count",
+        category,
+        "followers",
+        new BN(1_000),
+        new BN(BOND_PRICE),
+        "https://example.com/bond.json"
+      )
+      .accountsPartial({
+        bondsState: bondsStatePda,
+        bond,
+        market: marketPda(bond),
+        creatorBonds: creatorBondsPda(owner.publicKey),
+        creatorBondsPage: creatorBondsPagePda(owner.publicKey, pageIndex),
+        oracle: oracle.publicKey,
+        creator: owner.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([owner])
+      .rpc();
+
+    return { bondId, bond, market: marketPda(bond) };
+  }
+
+  function tradeAccounts(
+    bond: PublicKey,
+    buyer: PublicKey,
+    tokenAccount: PublicKey,
+    page: PublicKey
+  ) {
+    return {
+      bond,
+      market: marketPda(bond),
+      holderBonds: holderBondsPda(bond, buyer),
+      holderPage: { page },
+      bondsState: bondsStatePda,
+      feeLedger: feeLedgerPda(quoteMint),
+      protocolVault: { vault, vaultAuthority: vaultAuthorityPda },
+      buyerTokenAccount: tokenAccount,
+      sellerTokenAccount: tokenAccount,
+      buyer,
+      referrerStats: null,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    };
+  }
+
+  async function buy(bond: PublicKey, amount: number) {
+    const market = await program.account.market.fetch(marketPda(bond));
+    await program.methods
+      .tradeBond(new BN(amount), true)
+      .accountsPartial(
+        tradeAccounts(
+          bond,
+          trader.publicKey,
+          traderQuote,
+          holderPagePda(bond, market.holderTailPage)
+        )
+      )
+      .signers([trader])
+      .rpc();
+  }
+
+  before(async () => {
+    for (const wallet of [creator, trader, recipient]) {
+      await airdrop(wallet.publicKey);
+    }
+
+    await program.methods
+      .initialize()
+      .accountsPartial({
+        bondsState: bondsStatePda,
+        authority: authority.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    quoteMint = await createMint(connection, authority, authority.publicKey, null, 6);
+    otherMint = await createMint(connection, authority, authority.publicKey, null, 6);
+    vault = await createAccount(connection, authority, quoteMint, vaultAuthorityPda, Keypair.generate());
+    otherVault = await createAccount(connection, authority, otherMint, vaultAuthorityPda, Keypair.generate());
+    traderQuote = await createAccount(connection, authority, quoteMint, trader.publicKey);
+    traderOther = await createAccount(connection, authority, otherMint, trader.publicKey);
+    creatorQuote = await createAccount(connection, authority, quoteMint, creator.publicKey);
+    await mintTo(connection, authority, quoteMint, traderQuote, authority, 1_000_000_000);
+    await mintTo(connection, authority, quoteMint, creatorQuote, authority, 1_000_000_000);
+  });
+
+  describe("bond creation", () => {
+    it("indexes every bond a creator issues", async () => {
+      const first = await createBond(creator);
+      const second = await createBond(creator);
+
+      const creatorBonds = await program.account.creatorBonds.fetch(
+        creatorBondsPda(creator.publicKey)
+      );
+      const page = await program.account.creatorBondsPage.fetch(
+        creatorBondsPagePda(creator.publicKey, 0)
+      );
+      expect(creatorBonds.bondCount.toNumber()).to.equal(2);
+      expect(page.bondIds.map((id) => id.toNumber())).to.deep.equal([
+        first.bondId.toNumber(),
+        second.bondId.toNumber(),
+      ]);
+    });
+
+    it("rejects an unknown category", async () => {
+      await expectError(createBond(creator, "finance"), "InvalidCategory");
+    });
+
+    it("derives the bond and market from the bond id", async () => {
+      const { bondId, bond, market } = await createBond(creator);
+      const bondAccount = await program.account.bond.fetch(bond);
+      const marketAccount = await program.account.market.fetch(market);
+      expect(bondAccount.id.toString()).to.equal(bondId.toString());
+      expect(marketAccount.bond.toBase58()).to.equal(bond.toBase58());
+    });
+
+    it("rejects a bond address that skips the next id", async () => {
+      const bondsState = await program.account.bondsState.fetch(bondsStatePda);
+      const bond = bondPda(bondsState.bondCounter.addn(2));
+      await expectError(
+        program.methods
+          .createBond("Followers", "Follower count", "social", "followers", new BN(1_000), new BN(BOND_PRICE), "")
+          .accountsPartial({
+            bondsState: bondsStatePda,
+            bond,
+            market: marketPda(bond),
+            creatorBonds: creatorBondsPda(creator.publicKey),
+            creatorBondsPage: creatorBondsPagePda(creator.publicKey, 0),
+            oracle: oracle.publicKey,
+            creator: creator.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([creator])
+          .rpc(),
+        "ConstraintSeeds"
+      );
+    });
+  });
+
+  describe("transfers", () => {
+    let bond: PublicKey;
+
+    before(async () => {
+      ({ bond } = await createBond(creator));
+      await buy(bond, 10);
+    });
+
+    const transfer = (to: PublicKey, amount: number) =>
+      program.methods
+        .transferBond(new BN(amount))
+        .accountsPartial({
+          bond,
+          market: marketPda(bond),
+          fromHolderBonds: holderBondsPda(bond, trader.publicKey),
+          recipient: to,
+          toHolderBonds: holderBondsPda(bond, to),
+          fromHolderPage: { page: holderPagePda(bond, 0) },
+          toHolderPage: { page: holderPagePda(bond, 0) },
+          owner: trader.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([trader])
+        .rpc();
+
+    it("moves units and adds the recipient to the holder index", async () => {
+      await transfer(recipient.publicKey, 4);
+
+      const from = await program.account.holderBonds.fetch(holderBondsPda(bond, trader.publicKey));
+      const to = await program.account.holderBonds.fetch(holderBondsPda(bond, recipient.publicKey));
+      const market = await program.account.market.fetch(marketPda(bond));
+      expect(from.amount.toNumber()).to.equal(6);
+      expect(to.amount.toNumber()).to.equal(4);
+      expect(market.holderCount.toNumber()).to.equal(2);
+    });
+
+    it("rejects transferring more than the position holds", async () => {
+      await expectError(transfer(recipient.publicKey, 7), "InsufficientBonds");
+    });
+  });
+
+  describe("commit-reveal trading", () => {
+    let bond: PublicKey;
+    const salt = Buffer.alloc(32, 7);
+    const commitmentFor = (amount: number, isBuy: boolean, maxPrice: number) =>
+      Array.from(
+        createHash("sha256")
+          .update(u64(amount))
+          .update(Buffer.from([isBuy ? 1 : 0]))
+          .update(u64(maxPrice))
+          .update(salt)
+          .digest()
+      );
+    const tradeCommitmentPda = () =>
+      pda(Buffer.from("trade_commitment"), bond.toBuffer(), trader.publicKey.toBuffer());
+    const commit = (deposit: number) =>
+      program.methods
+        .commitTrade(commitmentFor(5, true, BOND_PRICE), new BN(deposit))
+        .accountsPartial({
+          bond,
+          tradeCommitment: tradeCommitmentPda(),
+          trader: trader.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([trader])
+        .rpc();
+
+    before(async () => {
+      ({ bond } = await createBond(creator));
+      await program.methods
+        .setTradingMode({ commitReveal: {} })
+        .accountsPartial({ bond, market: marketPda(bond), creator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+    });
+
+    it("rejects a deposit below the minimum", async () => {
+      await expectError(commit(MIN_COMMIT_DEPOSIT - 1), "InsufficientDeposit");
+    });
+
+    it("escrows the deposit with the commitment", async () => {
+      await commit(MIN_COMMIT_DEPOSIT);
+      const tradeCommitment = await program.account.tradeCommitment.fetch(tradeCommitmentPda());
+      expect(tradeCommitment.deposit.toNumber()).to.equal(MIN_COMMIT_DEPOSIT);
+      expect(tradeCommitment.commitment).to.deep.equal(commitmentFor(5, true, BOND_PRICE));
+    });
+
+    it("rejects a reveal that does not match the commitment", async () => {
+      const tradeCommitment = await program.account.tradeCommitment.fetch(tradeCommitmentPda());
+      while ((await connection.getSlot("confirmed")) <= tradeCommitment.commitSlot.toNumber()) {
+        await new Promise((resolve) => setTimeout(resolve, 200));
+      }
+
+      await expectError(
+        program.methods
+          .revealTrade(new BN(6), true, new BN(BOND_PRICE), Array.from(salt))
+          .accountsPartial({
+            trade: tradeAccounts(bond, trader.publicKey, traderQuote, holderPagePda(bond, 0)),
+            tradeCommitment: tradeCommitmentPda(),
+            trader: trader.publicKey,
+          })
+          .signers([trader])
+          .rpc(),
+        "CommitmentMismatch"
+      );
+    });
+  });
+
+  describe("buyback and burn", () => {
+    let bond: PublicKey;
+    const orderBookPda = () => pda(Buffer.from("order_book"), bond.toBuffer());
+
+    const placeAsk = (price: number, amount: number) => {
+      const orderBook = orderBookPda();
+      return program.methods
+        .placeOrder(false, new BN(price), new BN(amount))
+        .accountsPartial({
+          bond,
+          orderBook,
+          quoteVault: pda(Buffer.from("book_vault"), orderBook.toBuffer()),
+          holderBonds: holderBondsPda(bond, trader.publicKey),
+          ownerQuoteAccount: traderQuote,
+          owner: trader.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([trader])
+        .rpc();
+    };
+
+    const buyback = (amount: number) =>
+      program.methods
+        .buybackAndBurn(new BN(amount))
+        .accountsPartial({
+          bond,
+          market: marketPda(bond),
+          protocolVault: { vault, vaultAuthority: vaultAuthorityPda },
+          creatorTokenAccount: creatorQuote,
+          creator: creator.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([creator])
+        .rpc();
+
+    const buybackFromOrderBook = (amount: number) =>
+      program.methods
+        .buybackFromOrderBook(new BN(amount))
+        .accountsPartial({
+          bond,
+          market: marketPda(bond),
+          orderBook: orderBookPda(),
+          sellerHolderBonds: holderBondsPda(bond, trader.publicKey),
+          sellerHolderPage: { page: holderPagePda(bond, 0) },
+          sellerQuoteAccount: traderQuote,
+          creatorTokenAccount: creatorQuote,
+          creator: creator.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([creator])
+        .rpc();
+
+    before(async () => {
+      ({ bond } = await createBond(creator));
+      await buy(bond, 20);
+
+      const orderBook = orderBookPda();
+      await program.methods
+        .createOrderBook(new BN(1))
+        .accountsPartial({
+          bond,
+          orderBook,
+          quoteMint,
+          quoteVault: pda(Buffer.from("book_vault"), orderBook.toBuffer()),
+          creator: creator.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        })
+        .signers([creator])
+        .rpc();
+    });
+
+    it("buys unsold units from the vault at the current price and retires them", async () => {
+      const before = await getAccount(connection, vault);
+
+      await buyback(5);
+
+      const bondAccount = await program.account.bond.fetch(bond);
+      const after = await getAccount(connection, vault);
+      expect(bondAccount.currentSupply.toNumber()).to.equal(975);
+      expect(bondAccount.totalSupply.toNumber()).to.equal(995);
+      expect(Number(after.amount) - Number(before.amount)).to.equal(5 * BOND_PRICE);
+    });
+
+    it("rejects a buyback larger than the unsold supply", async () => {
+      await expectError(buyback(976), "InsufficientSupply");
+    });
+
+    it("rejects lifting an ask above the bond price", async () => {
+      await placeAsk(BOND_PRICE + 1, 5);
+      await expectError(buybackFromOrderBook(5), "NoBuybackOrders");
+    });
+
+    it("buys a holder's ask and retires the units", async () => {
+      await placeAsk(BOND_PRICE, 5);
+      const before = await getAccount(connection, traderQuote);
+
+      await buybackFromOrderBook(5);
+
+      const bondAccount = await program.account.bond.fetch(bond);
+      const holder = await program.account.holderBonds.fetch(holderBondsPda(bond, trader.publicKey));
+      const after = await getAccount(connection, traderQuote);
+      expect(bondAccount.totalSupply.toNumber()).to.equal(990);
+      expect(holder.amount.toNumber()).to.equal(15);
+      expect(holder.locked.toNumber()).to.equal(5);
+      expect(Number(after.amount) - Number(before.amount)).to.equal(5 * BOND_PRICE);
+    });
+  });
+
+  describe("insurance", () => {
+    let bond: PublicKey;
+    let incident: PublicKey;
+    let insuranceClaim: PublicKey;
+
+    const claim = (protocolVault: PublicKey, holderTokenAccount: PublicKey) =>
+      program.methods
+        .claimInsurance()
+        .accountsPartial({
+          incident,
+          insuranceClaim,
+          protocolVault: { vault: protocolVault, vaultAuthority: vaultAuthorityPda },
+          holderTokenAccount,
+          holder: trader.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([trader])
+        .rpc();
+
+    before(async () => {
+      ({ bond } = await createBond(creator));
+      await buy(bond, 10);
+
+      incident = pda(Buffer.from("incident"), bond.toBuffer(), u64(1));
+      insuranceClaim = pda(Buffer.from("insurance_claim"), incident.toBuffer(), trader.publicKey.toBuffer());
+      await program.methods
+        .openInsuranceIncident(new BN(1))
+        .accountsPartial({
+          bondsState: bondsStatePda,
+          bond,
+          incident,
+          authority: authority.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+      await program.methods
+        .compensateHolder(new BN(20))
+        .accountsPartial({
+          bondsState: bondsStatePda,
+          feeLedger: feeLedgerPda(quoteMint),
+          bond,
+          incident,
+          holderBonds: holderBondsPda(bond, trader.publicKey),
+          insuranceClaim,
+          authority: authority.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+    });
+
+    it("rejects a claim paid from another mint's vault", async () => {
+      await expectError(claim(otherVault, traderOther), "InvalidVault");
+    });
+
+    it("pays the claim from the vault of the fund's mint", async () => {
+      const before = await getAccount(connection, traderQuote);
+      await claim(vault, traderQuote);
+      const after = await getAccount(connection, traderQuote);
+      expect(Number(after.amount) - Number(before.amount)).to.equal(20);
+    });
+  });
+});